@@ -21,7 +21,7 @@ use {
     std::any::type_name,
 };
 
-use super::TracePlugins;
+use super::{move_tracers, ExplosionEvent, TracePlugins};
 
 #[derive(Default, Component)]
 pub struct Actor;
@@ -39,8 +39,9 @@ impl Plugin for ActorPlugins {
         #[cfg(feature = "temp-container")]
         app.add_systems(Startup, setup);
         app.add_event::<UnloadActorsEvent>()
+            .add_event::<ExplosionEvent>()
             .add_plugins(TracePlugins)
-            .add_systems(Update, unload_actors);
+            .add_systems(Update, (unload_actors, move_tracers));
     }
 }
 
@@ -0,0 +1,140 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{DamageEvent, Health},
+    component::{Despawn, DespawnReason, DespawnTimer},
+    extend_commands,
+    lobby::PlayerId,
+};
+
+use super::Actor;
+
+/// Seconds the flash of an explosion is visible.
+const FLASH_TIME: f32 = 0.3;
+
+/// Makes a projectile explode on its first contact or when its fuse burns down.
+///
+/// Every dynamic body in the radius is pushed away and characters take splash damage,
+/// both fall off linearly with the distance to the explosion.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Explosive {
+    pub radius: f32,
+    /// Impulse at the center of the explosion.
+    pub impulse: f32,
+    /// Damage at the center of the explosion.
+    pub damage: f32,
+    /// Seconds after which the projectile explodes without a contact.
+    pub fuse: f32,
+}
+
+impl Default for Explosive {
+    fn default() -> Self {
+        Self {
+            radius: 6.,
+            impulse: 60.,
+            damage: 50.,
+            fuse: 3.,
+        }
+    }
+}
+
+/// Sent when an explosive projectile is despawned, by a contact or by its fuse.
+#[derive(Debug, Event)]
+pub struct ExplosionEvent {
+    pub position: Vec3,
+    pub explosive: Explosive,
+    /// The player that has fired the projectile.
+    pub owner: Option<PlayerId>,
+}
+
+/// Despawns explosive projectiles on their first contact, the despawn sets them off.
+pub fn detonate_on_contact(
+    mut collision_started: EventReader<CollisionStarted>,
+    mut query: Query<&mut Despawn, With<Explosive>>,
+) {
+    for CollisionStarted(entity1, entity2) in collision_started.read() {
+        for entity in [entity1, entity2] {
+            if let Ok(mut despawn) = query.get_mut(*entity) {
+                despawn.insert_reason(DespawnReason::Forced);
+            }
+        }
+    }
+}
+
+/// Pushes the dynamic bodies in the radius of explosions and damages the characters among them.
+pub fn explode(
+    mut commands: Commands,
+    mut explosion_event: EventReader<ExplosionEvent>,
+    mut body_query: Query<(
+        Entity,
+        &RigidBody,
+        &Position,
+        &mut ExternalImpulse,
+        Has<Health>,
+    )>,
+    mut damage_event: EventWriter<DamageEvent>,
+) {
+    for ExplosionEvent {
+        position,
+        explosive,
+        owner,
+    } in explosion_event.read()
+    {
+        commands.spawn_explosion_flash(*position, explosive.radius);
+        for (entity, rigid_body, body_position, mut impulse, has_health) in body_query.iter_mut() {
+            if *rigid_body != RigidBody::Dynamic {
+                continue;
+            }
+            let offset = body_position.0 - *position;
+            let distance = offset.length();
+            if distance >= explosive.radius {
+                continue;
+            }
+
+            let falloff = 1. - distance / explosive.radius;
+            // a body right in the center is thrown up
+            let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+            impulse.apply_impulse(direction * explosive.impulse * falloff);
+            if has_health {
+                damage_event.send(DamageEvent {
+                    target: entity,
+                    amount: explosive.damage * falloff,
+                    owner: *owner,
+                });
+            }
+        }
+    }
+}
+
+extend_commands!(
+    spawn_explosion_flash(position: Vec3, radius: f32),
+    |world: &mut World, entity_id: Entity, position: Vec3, radius: f32| {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::try_from(shape::Icosphere { radius, subdivisions: 3 }).unwrap());
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::rgba(1., 0.6, 0.1, 0.4),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            });
+
+        world.entity_mut(entity_id).insert((
+            PbrBundle {
+                mesh,
+                material,
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            Despawn::new(DespawnReason::After(DespawnTimer::new(FLASH_TIME))),
+            Name::new("explosion"),
+            Actor,
+        ));
+    }
+);
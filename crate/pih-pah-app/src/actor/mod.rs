@@ -1,11 +1,15 @@
 #![allow(clippy::module_inception)]
 
 mod actor;
+mod explosion;
 mod projectile;
 mod trace;
+mod tracer;
 
 pub mod physics_bundle;
 
 pub use actor::*;
+pub use explosion::*;
 pub use projectile::*;
 pub use trace::*;
+pub use tracer::*;
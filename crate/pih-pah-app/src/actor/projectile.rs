@@ -6,13 +6,13 @@ use bevy_xpbd_3d::components::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    component::{AxisName, Despawn, DespawnReason},
+    component::{AxisName, Despawn, DespawnReason, DespawnTimer},
     extend_commands,
-    lobby::host::SpawnProjectileEvent,
+    lobby::{host::SpawnProjectileEvent, PlayerId},
     world::{LinkId, ProjectileIdSeq},
 };
 
-use super::{physics_bundle::PhysicsBundle, Actor, TransformOptimalTrace};
+use super::{physics_bundle::PhysicsBundle, Actor, Explosive, TransformOptimalTrace};
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Projectile {
@@ -20,7 +20,15 @@ pub struct Projectile {
     pub direction: Vec3,
     pub power: f32,
     pub mass: f32,
+    /// Gravity of the projectile relative to the world gravity.
+    pub gravity_scale: f32,
     pub color: Color,
+    /// The player that has fired the projectile.
+    pub owner: Option<PlayerId>,
+    /// Makes the projectile explode, see [`Explosive`].
+    pub explosive: Option<Explosive>,
+    /// Number of the shot of the owner's client, see [`Inputs::shot`](crate::lobby::Inputs::shot).
+    pub shot: u32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -29,24 +37,53 @@ pub struct ProjectileShell {
     pub id: LinkId,
 }
 
+/// A projectile spawned by a client when it fires, before the server has spawned the real one.
+///
+/// It is turned into the server's projectile when the server confirms the shot,
+/// and despawned when the server rejects it.
+#[derive(Debug, Component)]
+pub struct PredictedProjectile {
+    /// Time when the projectile has been fired.
+    pub fired: f32,
+    /// Number of the shot, the server confirms or rejects shots by it.
+    pub shot: u32,
+}
+
+/// Color of a projectile simulated by the host, sent in the [`WorldState`](crate::lobby::WorldState).
+#[derive(Debug, Component)]
+pub struct ProjectileColor(pub Color);
+
+/// Owner and momentum of a projectile simulated by the host, characters hit by it take damage.
+#[derive(Debug, Component)]
+pub struct Impact {
+    pub owner: Option<PlayerId>,
+    pub mass: f32,
+    /// Velocity before the latest physics step.
+    pub velocity: Vec3,
+}
+
 const SIZE: f32 = 0.5;
 
-extend_commands!(
-    spawn_projectile(projectile: Projectile),
-    |world: &mut World, entity_id: Entity, projectile: Projectile| {
-        let mesh = world
-            .resource_mut::<Assets<Mesh>>()
-            .add(Mesh::try_from(shape::Cube { size: SIZE }).unwrap());
-        let material = world
-            .resource_mut::<Assets<StandardMaterial>>()
-            .add(StandardMaterial {
-                base_color: projectile.color,
-                ..default()
-            });
+/// Returns the trace of a projectile shell moved by snapshots.
+pub fn projectile_shell_trace(color: Color) -> TransformOptimalTrace {
+    TransformOptimalTrace::new(0.2, 0.005, color, SIZE / 2.)
+}
 
-        let link_id = world.resource_mut::<ProjectileIdSeq>().shift();
+/// Inserts the mesh and the physics of a simulated projectile.
+fn insert_simulated_projectile(world: &mut World, entity_id: Entity, projectile: &Projectile) {
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Mesh::try_from(shape::Cube { size: SIZE }).unwrap());
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(StandardMaterial {
+            base_color: projectile.color,
+            ..default()
+        });
 
-        world.entity_mut(entity_id).insert((
+    world
+        .entity_mut(entity_id)
+        .insert((
             PbrBundle {
                 mesh,
                 material,
@@ -62,17 +99,61 @@ extend_commands!(
                 DespawnReason::Less(-100., AxisName::Z),
             )),
             // PhysicsOptimalTrace::new(0.2, 0.005, projectile.color, SIZE / 2.),
-            GravityDirection::new(Vec3::Y * -0.2),
+            GravityDirection::new(Vec3::NEG_Y * projectile.gravity_scale),
             Actor,
-            link_id.clone(),
         ))
         .insert((
             PhysicsBundle::from_rigid_body(RigidBody::Dynamic),
             Collider::cuboid(SIZE, SIZE, SIZE),
             MassPropertiesBundle::default(),
-            LinearVelocity::from(projectile.direction * projectile.power)));
+            LinearVelocity::from(projectile.direction * projectile.power),
+        ));
+}
+
+extend_commands!(
+    spawn_projectile(projectile: Projectile),
+    |world: &mut World, entity_id: Entity, projectile: Projectile| {
+        insert_simulated_projectile(world, entity_id, &projectile);
+
+        let link_id = world.resource_mut::<ProjectileIdSeq>().shift();
+        world.entity_mut(entity_id).insert((
+            link_id.clone(),
+            ProjectileColor(projectile.color),
+            Impact {
+                owner: projectile.owner,
+                mass: projectile.mass,
+                velocity: projectile.direction * projectile.power,
+            },
+        ));
+
+        if let Some(explosive) = projectile.explosive {
+            if let Some(mut despawn) = world.get_mut::<Despawn>(entity_id) {
+                despawn.insert_reason(DespawnReason::After(DespawnTimer::new(explosive.fuse)));
+            }
+            world.entity_mut(entity_id).insert(explosive);
+        }
+
+        world.send_event(SpawnProjectileEvent(
+            link_id,
+            projectile.color,
+            projectile.owner,
+            projectile.shot,
+        ));
+    }
+);
 
-        world.send_event(SpawnProjectileEvent(link_id, projectile.color));
+extend_commands!(
+    spawn_predicted_projectile(projectile: Projectile, fired: f32),
+    |world: &mut World, entity_id: Entity, projectile: Projectile, fired: f32| {
+        insert_simulated_projectile(world, entity_id, &projectile);
+
+        world.entity_mut(entity_id).insert((
+            PredictedProjectile {
+                fired,
+                shot: projectile.shot,
+            },
+            projectile_shell_trace(projectile.color),
+        ));
     }
 );
 
@@ -98,7 +179,7 @@ extend_commands!(
             // Trace::new(0.5, 0.05, projectile.color),
             projectile.id,
             Actor,
-            TransformOptimalTrace::new(0.2, 0.005, projectile.color, SIZE / 2.),
+            projectile_shell_trace(projectile.color),
         ));
     }
 );
@@ -0,0 +1,58 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    component::{Despawn, DespawnReason, DespawnTimer},
+    extend_commands,
+    lobby::PlayerId,
+};
+
+use super::{Actor, Trace};
+
+/// Seconds a tracer takes to fly from the muzzle to the hit point.
+const TRACER_TIME: f32 = 0.1;
+
+/// The visible path of a hitscan shot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tracer {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: Color,
+    /// The player that has fired the shot, its client shows its own tracers without waiting.
+    pub owner: Option<PlayerId>,
+}
+
+/// Moves the carrier of a [`Trace`] along a [`Tracer`], the trace leaves the tracepoints behind it.
+#[derive(Debug, Component)]
+pub struct TracerFlight {
+    start: Vec3,
+    end: Vec3,
+    elapsed: f32,
+}
+
+pub fn move_tracers(mut query: Query<(&mut Transform, &mut TracerFlight)>, time: Res<Time>) {
+    for (mut transform, mut flight) in query.iter_mut() {
+        flight.elapsed += time.delta_seconds();
+        let progress = (flight.elapsed / TRACER_TIME).min(1.);
+        transform.translation = flight.start.lerp(flight.end, progress);
+    }
+}
+
+extend_commands!(
+    spawn_tracer(tracer: Tracer),
+    |world: &mut World, entity_id: Entity, tracer: Tracer| {
+        world.entity_mut(entity_id).insert((
+            TransformBundle::from_transform(Transform::from_translation(tracer.start)),
+            TracerFlight {
+                start: tracer.start,
+                end: tracer.end,
+                elapsed: 0.,
+            },
+            Trace::new(0.3, 0.005, tracer.color),
+            Despawn::new(DespawnReason::After(DespawnTimer::new(TRACER_TIME))),
+            Name::new("tracer"),
+            Actor,
+        ));
+    }
+);
@@ -1,8 +1,9 @@
 use crate::actor::physics_bundle::PhysicsBundle;
-use crate::actor::{spawn_projectile, Projectile};
+use crate::actor::{detonate_on_contact, explode, spawn_projectile, spawn_tracer};
 use crate::component::{AxisName, DespawnReason, NoclipDuration, Respawn};
 use crate::extend_commands;
-use crate::lobby::host::{generate_player_color, server_update_system};
+use crate::lobby::clock::GameClock;
+use crate::lobby::host::{server_update_system, SpawnTracerEvent};
 use crate::lobby::{Character, InputType, PlayerInputs};
 use crate::lobby::{LobbyState, PlayerId, PlayerView};
 use crate::map::SpawnPoint;
@@ -14,6 +15,11 @@ use bevy_xpbd_3d::parry::na::ComplexField;
 use bevy_xpbd_3d::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::{
+    apply_damage, projectile_damage, respawn_dead, revive, track_impacts, DamageEvent, Dead,
+    DeathEvent, Health, WeaponState, Weapons,
+};
+
 pub const PLAYER_MOVE_SPEED: f32 = 0.07;
 pub const PLAYER_SIZE: f32 = 2.0;
 const SHIFT_ACCELERATION: f32 = 2.0;
@@ -34,28 +40,44 @@ pub struct CharacterPlugins;
 
 impl Plugin for CharacterPlugins {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (move_characters, update_jump_normals).run_if(
-                not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
-            ),
-        )
-        .add_systems(
-            Update,
-            (jump, rotate_camera, gravity_direction).run_if(
-                not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
-            ),
-        )
-        .add_systems(
-            Last,
-            fire.after(server_update_system).run_if(
-                not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
-            ),
-        )
-        .add_systems(
-            PostUpdate,
-            tied_camera_follow.run_if(not(in_state(LobbyState::None))),
-        );
+        app.add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems(
+                FixedUpdate,
+                (move_characters, update_jump_normals).run_if(
+                    not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
+                ),
+            )
+            .add_systems(
+                Update,
+                (jump, rotate_camera, gravity_direction).run_if(
+                    not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    track_impacts.before(PhysicsSet::Prepare),
+                    projectile_damage.after(PhysicsSet::StepSimulation),
+                    detonate_on_contact.after(PhysicsSet::StepSimulation),
+                    explode.before(PhysicsSet::Prepare),
+                    respawn_dead,
+                    revive,
+                )
+                    .run_if(
+                        not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
+                    ),
+            )
+            .add_systems(
+                Last,
+                (fire.after(server_update_system), apply_damage.after(fire)).run_if(
+                    not(in_state(LobbyState::None)).and_then(not(in_state(LobbyState::Client))),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                tied_camera_follow.run_if(not(in_state(LobbyState::None))),
+            );
     }
 }
 
@@ -181,23 +203,80 @@ fn move_characters(
     }
 }
 
+/// Switches, reloads and fires the weapons of characters.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn fire(
     mut commands: Commands,
-    mut query: Query<(&mut PlayerInputs, &PlayerView, &Transform)>,
+    mut query: Query<
+        (
+            Entity,
+            &PlayerInputs,
+            &PlayerView,
+            &Transform,
+            Option<&Character>,
+            &mut WeaponState,
+        ),
+        Without<Dead>,
+    >,
+    mut body_query: Query<(&mut ExternalImpulse, &Position, &Rotation, &CenterOfMass)>,
+    spatial_query: SpatialQuery,
+    mut damage_event: EventWriter<DamageEvent>,
+    mut tracer_event: EventWriter<SpawnTracerEvent>,
+    weapons: Res<Weapons>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
 ) {
-    for (mut input, view, transform) in query.iter_mut() {
-        // TODO bad way change input, must have independent state
-        if input.is_input_changed_to_true_and_set_to_false(InputType::Fire) {
-            log::info!("projectile spawned");
-            let random_i32 = rand::random::<i32>();
-            let color = generate_player_color(random_i32 as u32);
-            commands.spawn_projectile(Projectile {
-                position: transform.translation + Vec3::Y * 2.,
-                direction: view.direction * Vec3::NEG_Z,
-                power: 80.,
-                mass: 1.,
-                color,
-            });
+    let now = clock.now(&time);
+    for (entity, input, view, transform, character, mut state) in query.iter_mut() {
+        let input = input.get();
+        let owner = character.map(|character| character.id);
+        // the state is replicated on change, it is only written when something happens
+        let mut new_state = state.clone();
+        if let Some(index) = input.weapon {
+            new_state.select(index as usize, &weapons);
+        }
+        new_state.update(&weapons, now);
+        if input.reload {
+            new_state.reload(&weapons, now);
+        }
+        if let Some(charge) = new_state.trigger(&weapons, input.fire, now) {
+            if let Some(weapon) = weapons.get(new_state.selected) {
+                match &weapon.hitscan {
+                    Some(hitscan) => {
+                        let pellets =
+                            hitscan.shot(weapon, entity, transform, view, owner, &spatial_query);
+                        for pellet in pellets {
+                            if let Some(target) = pellet.hit {
+                                damage_event.send(DamageEvent {
+                                    target,
+                                    amount: hitscan.damage,
+                                    owner,
+                                });
+                                if let Ok((mut impulse, position, rotation, center_of_mass)) =
+                                    body_query.get_mut(target)
+                                {
+                                    impulse.apply_impulse_at_point(
+                                        pellet.direction * hitscan.impulse,
+                                        pellet.tracer.end - position.0,
+                                        rotation.0 * center_of_mass.0,
+                                    );
+                                }
+                            }
+                            commands.spawn_tracer(pellet.tracer.clone());
+                            tracer_event.send(SpawnTracerEvent(pellet.tracer));
+                        }
+                    }
+                    None => {
+                        for projectile in weapon.shot(transform, view, owner, charge, input.shot) {
+                            commands.spawn_projectile(projectile);
+                        }
+                    }
+                }
+                new_state.fire(&weapons, now);
+            }
+        }
+        if new_state != *state {
+            *state = new_state;
         }
     }
 }
@@ -258,6 +337,11 @@ extend_commands!(
       .resource_mut::<Assets<StandardMaterial>>()
       .add(color.into());
 
+    let weapon_state = world
+      .get_resource::<Weapons>()
+      .map(WeaponState::new)
+      .unwrap_or_default();
+
       // some raycast magic
     let start_point = Vec3::Y * 2.;
     let offset = Vec3::new(0., 0., DEFAULT_CAMERA_DISTANCE);
@@ -272,6 +356,8 @@ extend_commands!(
             },
             RayCaster::new(start_point, offset),
             JumpHelper{last_viable_normal: Vec3::Y},
+            Health::default(),
+            weapon_state,
             Respawn::new((
                 DespawnReason::More(200., AxisName::Y),
                 DespawnReason::Less(-10., AxisName::Y),
@@ -324,6 +410,7 @@ extend_commands!(
        },
         // TransformOptimalTrace::new(0.5, 0.05, color, PLAYER_SIZE / 2.),
         PlayerInputs::default(),
+        Health::default(),
         Name::new(format!("Character:{:#?}", player_id)),
         PlayerView::new(Quat::default(), 325.0.sqrt())));
   }
@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::actor::Impact;
+use crate::component::{DespawnReason, Respawn, RespawnEvent};
+use crate::lobby::clock::GameClock;
+use crate::lobby::{Character, Lobby, PlayerId};
+use crate::mode::{FriendlyFire, GameMode, Scoreboard};
+use crate::settings::ServerSettings;
+
+pub const MAX_HEALTH: f32 = 100.;
+/// Damage per joule of kinetic energy of a projectile hit, a full power shot takes about a third of [`MAX_HEALTH`].
+const DAMAGE_PER_ENERGY: f32 = 0.01;
+/// Impact speed below which a projectile does not damage, it just lies on the character.
+const MIN_IMPACT_SPEED: f32 = 5.;
+
+/// Health of a character, the character dies when it drops to zero.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new(MAX_HEALTH)
+    }
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.
+    }
+
+    /// Returns the part of the health that is left, from `0.` to `1.`.
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0. {
+            (self.current / self.max).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+
+    /// Takes the damage, returns `true` if it has killed the character.
+    pub fn damage(&mut self, amount: f32) -> bool {
+        let was_dead = self.is_dead();
+        self.current = (self.current - amount).max(0.);
+        !was_dead && self.is_dead()
+    }
+
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+}
+
+/// A character that has died and waits for its respawn, it is hidden and does not move meanwhile.
+///
+/// The entity stays, so the view direction, gravity direction and color
+/// of the player carry over to the next life.
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Dead {
+    pub killer: Option<PlayerId>,
+    /// Server time of the respawn, see [`GameClock`].
+    pub respawn_at: f64,
+}
+
+/// Sent on the host when a character has died, it is respawned with [`DespawnReason::Killed`]
+/// after [`GameMode::respawn_delay`].
+#[derive(Debug, Event)]
+pub struct DeathEvent {
+    pub victim: PlayerId,
+    pub killer: Option<PlayerId>,
+    /// Server time of the respawn.
+    pub respawn_at: f64,
+}
+
+/// Sent on the host when a character is hit by a projectile or a hitscan shot, see [`apply_damage`].
+#[derive(Debug, Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// The player that has fired the shot.
+    pub owner: Option<PlayerId>,
+}
+
+/// Remembers the velocity of projectiles before the physics step,
+/// the solver has already slowed them down when the collision is reported.
+pub fn track_impacts(mut query: Query<(&mut Impact, &LinearVelocity)>) {
+    for (mut impact, velocity) in query.iter_mut() {
+        impact.velocity = velocity.0;
+    }
+}
+
+/// Damages characters hit by projectiles by the kinetic energy of the hit.
+pub fn projectile_damage(
+    mut collision_started: EventReader<CollisionStarted>,
+    impact_query: Query<&Impact>,
+    character_query: Query<&LinearVelocity, With<Health>>,
+    mut damage_event: EventWriter<DamageEvent>,
+) {
+    for CollisionStarted(entity1, entity2) in collision_started.read() {
+        for (projectile, target) in [(*entity1, *entity2), (*entity2, *entity1)] {
+            let Ok(impact) = impact_query.get(projectile) else {
+                continue;
+            };
+            let Ok(velocity) = character_query.get(target) else {
+                continue;
+            };
+
+            let speed = (impact.velocity - velocity.0).length();
+            if speed < MIN_IMPACT_SPEED {
+                continue;
+            }
+            let energy = 0.5 * impact.mass * speed.powi(2);
+            damage_event.send(DamageEvent {
+                target,
+                amount: energy * DAMAGE_PER_ENERGY,
+                owner: impact.owner,
+            });
+        }
+    }
+}
+
+/// Applies the damage to the characters and kills the ones without health left.
+///
+/// Teammates do not damage each other without [`FriendlyFire`].
+#[allow(clippy::too_many_arguments)]
+pub fn apply_damage(
+    mut commands: Commands,
+    mut damage_event: EventReader<DamageEvent>,
+    mut character_query: Query<(&Character, &mut Health)>,
+    mut death_event: EventWriter<DeathEvent>,
+    lobby: Option<Res<Lobby>>,
+    friendly_fire: Option<Res<FriendlyFire>>,
+    game_mode: Option<Res<GameMode>>,
+    server_settings: Res<ServerSettings>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    for DamageEvent {
+        target,
+        amount,
+        owner,
+    } in damage_event.read()
+    {
+        let Ok((character, mut health)) = character_query.get_mut(*target) else {
+            continue;
+        };
+        let friendly_fire = friendly_fire
+            .as_ref()
+            .map_or(true, |friendly_fire| friendly_fire.0);
+        if let (Some(owner), Some(lobby)) = (owner, lobby.as_ref()) {
+            if !friendly_fire && *owner != character.id && lobby.are_teammates(*owner, character.id)
+            {
+                continue;
+            }
+        }
+        if !health.damage(*amount) {
+            continue;
+        }
+
+        let respawn_delay = game_mode
+            .as_ref()
+            .map_or(server_settings.respawn_delay, |mode| {
+                mode.respawn_delay(&server_settings)
+            });
+        let dead = Dead {
+            killer: *owner,
+            respawn_at: clock.now(&time) + respawn_delay as f64,
+        };
+        commands.entity(*target).insert((
+            dead,
+            Visibility::Hidden,
+            RigidBody::Static,
+            CollisionLayers::none(),
+        ));
+        death_event.send(DeathEvent {
+            victim: character.id,
+            killer: dead.killer,
+            respawn_at: dead.respawn_at,
+        });
+    }
+}
+
+/// Respawns dead characters when their respawn time has come.
+pub fn respawn_dead(
+    mut query: Query<(&Dead, &mut Respawn)>,
+    scoreboard: Option<Res<Scoreboard>>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    // everyone respawns when the next round starts
+    if scoreboard.is_some_and(|scoreboard| scoreboard.is_over()) {
+        return;
+    }
+    let now = clock.now(&time);
+    for (dead, mut respawn) in query.iter_mut() {
+        if now >= dead.respawn_at {
+            respawn.insert_reason(DespawnReason::Killed);
+        }
+    }
+}
+
+/// Restores the health of respawned characters and brings dead ones back to life.
+pub fn revive(
+    mut commands: Commands,
+    mut respawn_event: EventReader<RespawnEvent>,
+    mut query: Query<(&mut Health, Has<Dead>)>,
+) {
+    for RespawnEvent { entity, .. } in respawn_event.read() {
+        let Ok((mut health, was_dead)) = query.get_mut(*entity) else {
+            continue;
+        };
+        health.restore();
+        if was_dead {
+            // the respawn has already given the character its noclip collision layers
+            commands
+                .entity(*entity)
+                .remove::<Dead>()
+                .insert((Visibility::Inherited, RigidBody::Dynamic));
+        }
+    }
+}
@@ -1,4 +1,8 @@
 #![allow(clippy::module_inception)]
 
 mod character;
+mod health;
+mod weapon;
 pub use character::*;
+pub use health::*;
+pub use weapon::*;
@@ -0,0 +1,421 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::actor::{Explosive, Projectile, Tracer};
+use crate::lobby::host::generate_player_color;
+use crate::lobby::{PlayerId, PlayerView};
+use crate::map::asset_root;
+
+/// Folder inside [`ASSET_FOLDER`](crate::map::ASSET_FOLDER) with one yaml file per weapon,
+/// weapons get their number keys in the order of the file names.
+pub const WEAPON_FOLDER: &str = "weapon";
+/// Weapons that can be selected with the number keys.
+pub const MAX_WEAPONS: usize = 9;
+
+/// A weapon definition, read from a file in [`WEAPON_FOLDER`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weapon {
+    pub name: String,
+    /// Shots per second while the trigger is held.
+    pub fire_rate: f32,
+    pub projectile_speed: f32,
+    pub projectile_mass: f32,
+    /// Gravity of the projectiles relative to the world gravity.
+    pub gravity_scale: f32,
+    /// Maximal angle in degrees between a pellet and the view direction.
+    pub spread: f32,
+    /// Projectiles fired by one shot.
+    pub pellets: u32,
+    /// Shots before the weapon must be reloaded.
+    pub magazine: u32,
+    /// Seconds it takes to reload.
+    pub reload_time: f32,
+    /// Makes the weapon hit instantly instead of firing projectiles.
+    pub hitscan: Option<Hitscan>,
+    /// Makes the projectiles of the weapon explode.
+    pub explosive: Option<Explosive>,
+    /// Makes the weapon charge while the trigger is held and fire when it is released.
+    pub charge: Option<Charge>,
+}
+
+/// Charge of a weapon, the speed of its projectiles grows while the trigger is held.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Charge {
+    /// Seconds until the charge is full.
+    pub time: f32,
+    /// Projectile speed of a full charge relative to [`Weapon::projectile_speed`].
+    pub max_power: f32,
+}
+
+impl Default for Charge {
+    fn default() -> Self {
+        Self {
+            time: 1.,
+            max_power: 2.5,
+        }
+    }
+}
+
+impl Charge {
+    /// Returns the charge after the trigger has been held for the time, from `0.` to `1.`.
+    pub fn fraction(&self, held: f64) -> f32 {
+        if self.time > 0. {
+            (held as f32 / self.time).clamp(0., 1.)
+        } else {
+            1.
+        }
+    }
+}
+
+/// Hit of a hitscan weapon, applied to whatever the ray from the camera hits first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hitscan {
+    /// Damage of one pellet.
+    pub damage: f32,
+    /// Impulse of one pellet at the hit point.
+    pub impulse: f32,
+    /// Maximal distance of a hit.
+    pub range: f32,
+}
+
+impl Default for Hitscan {
+    fn default() -> Self {
+        Self {
+            damage: 35.,
+            impulse: 20.,
+            range: 150.,
+        }
+    }
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Self {
+            name: "Blaster".to_string(),
+            fire_rate: 5.,
+            projectile_speed: 80.,
+            projectile_mass: 1.,
+            gravity_scale: 0.2,
+            spread: 0.,
+            pellets: 1,
+            magazine: 12,
+            reload_time: 1.5,
+            hitscan: None,
+            explosive: None,
+            charge: None,
+        }
+    }
+}
+
+impl Weapon {
+    /// Returns the directions of the pellets of one shot, spread around the view direction.
+    fn pellet_directions(&self, view: &PlayerView) -> Vec<Vec3> {
+        let spread = self.spread.to_radians();
+        (0..self.pellets.max(1))
+            .map(|_| {
+                let deviation = if spread > 0. {
+                    Quat::from_euler(
+                        EulerRot::YXZ,
+                        rand::random::<f32>() * 2. * spread - spread,
+                        rand::random::<f32>() * 2. * spread - spread,
+                        0.,
+                    )
+                } else {
+                    Quat::IDENTITY
+                };
+                view.direction * deviation * Vec3::NEG_Z
+            })
+            .collect()
+    }
+
+    /// Returns the projectile speed of a shot with the charge, from `0.` to `1.`.
+    pub fn power(&self, charge: f32) -> f32 {
+        match &self.charge {
+            Some(Charge { max_power, .. }) => {
+                self.projectile_speed * (1. + (max_power - 1.) * charge)
+            }
+            None => self.projectile_speed,
+        }
+    }
+
+    /// Returns the projectiles of one shot fired by a character, clients predict them the same way.
+    pub fn shot(
+        &self,
+        transform: &Transform,
+        view: &PlayerView,
+        owner: Option<PlayerId>,
+        charge: f32,
+        shot: u32,
+    ) -> Vec<Projectile> {
+        let color = generate_player_color(rand::random::<u32>());
+        self.pellet_directions(view)
+            .into_iter()
+            .map(|direction| Projectile {
+                position: muzzle(transform),
+                direction,
+                power: self.power(charge),
+                mass: self.projectile_mass,
+                gravity_scale: self.gravity_scale,
+                color,
+                owner,
+                explosive: self.explosive.clone(),
+                shot,
+            })
+            .collect()
+    }
+
+    /// Returns the rays of one hitscan shot, they start at the camera of the character
+    /// so they hit what the player aims at.
+    pub fn rays(&self, transform: &Transform, view: &PlayerView) -> Vec<Ray> {
+        let camera = muzzle(transform) + view.direction * Vec3::Z * view.distance;
+        self.pellet_directions(view)
+            .into_iter()
+            .map(|direction| Ray {
+                origin: camera,
+                direction,
+            })
+            .collect()
+    }
+}
+
+/// A pellet of a hitscan shot.
+pub struct HitscanPellet {
+    pub tracer: Tracer,
+    /// The entity the pellet has hit first, at the end of the tracer.
+    pub hit: Option<Entity>,
+    pub direction: Vec3,
+}
+
+impl Hitscan {
+    /// Casts the rays of one shot of the weapon, the shooter is not hit by its own rays.
+    pub fn shot(
+        &self,
+        weapon: &Weapon,
+        shooter: Entity,
+        transform: &Transform,
+        view: &PlayerView,
+        owner: Option<PlayerId>,
+        spatial_query: &SpatialQuery,
+    ) -> Vec<HitscanPellet> {
+        let color = generate_player_color(rand::random::<u32>());
+        let filter = SpatialQueryFilter::default().without_entities([shooter]);
+        weapon
+            .rays(transform, view)
+            .into_iter()
+            .map(|ray| {
+                let hit = spatial_query.cast_ray(
+                    ray.origin,
+                    ray.direction,
+                    self.range,
+                    true,
+                    filter.clone(),
+                );
+                let distance = hit.as_ref().map_or(self.range, |hit| hit.time_of_impact);
+                HitscanPellet {
+                    tracer: Tracer {
+                        start: muzzle(transform),
+                        end: ray.origin + ray.direction * distance,
+                        color,
+                        owner,
+                    },
+                    hit: hit.map(|hit| hit.entity),
+                    direction: ray.direction,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the point where the shots of a character start, the camera rotates around it.
+pub fn muzzle(transform: &Transform) -> Vec3 {
+    transform.translation + Vec3::Y * 2.
+}
+
+/// Weapons of every character.
+///
+/// The host reads them from [`WEAPON_FOLDER`] and sends them to the clients when they connect,
+/// so clients predict shots with the host's weapons.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct Weapons(pub Vec<Weapon>);
+
+impl Default for Weapons {
+    fn default() -> Self {
+        Self(vec![Weapon::default()])
+    }
+}
+
+impl Weapons {
+    /// Reads the weapon files, files that can not be read are skipped.
+    /// Returns the default weapon if there are none.
+    pub fn load() -> Self {
+        let folder = asset_root().join(WEAPON_FOLDER);
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&folder) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    matches!(
+                        path.extension().and_then(|extension| extension.to_str()),
+                        Some("yaml" | "yml")
+                    )
+                })
+                .collect(),
+            Err(err) => {
+                log::warn!("Failed to read weapons from {:?}: {}", folder, err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        let weapons: Vec<Weapon> = paths
+            .iter()
+            .filter_map(|path| {
+                let result = File::open(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|file| serde_yaml::from_reader(file).map_err(|err| err.to_string()));
+                match result {
+                    Ok(weapon) => Some(weapon),
+                    Err(err) => {
+                        log::error!("Failed to read weapon {:?}: {}", path, err);
+                        None
+                    }
+                }
+            })
+            .take(MAX_WEAPONS)
+            .collect();
+
+        if weapons.is_empty() {
+            log::warn!("No weapons found, the default one is used");
+            return Self::default();
+        }
+        log::info!(
+            "Weapons: {}",
+            weapons
+                .iter()
+                .map(|weapon| weapon.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Self(weapons)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Weapon> {
+        self.0.get(index)
+    }
+}
+
+/// Selected weapon and ammo of a character, replicated to the clients.
+///
+/// Times are server timestamps, see [`GameClock`](crate::lobby::clock::GameClock).
+#[derive(Component, Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponState {
+    pub selected: usize,
+    /// Shots left in the magazine of every weapon.
+    pub ammo: Vec<u32>,
+    /// The selected weapon can not fire before this time.
+    pub next_shot: f64,
+    /// End of the reload of the selected weapon.
+    pub reload_end: Option<f64>,
+    /// Start of the charge of the selected weapon, see [`Charge`].
+    pub charge_start: Option<f64>,
+}
+
+impl WeaponState {
+    /// Creates a state with full magazines and the first weapon selected.
+    pub fn new(weapons: &Weapons) -> Self {
+        Self {
+            ammo: weapons.0.iter().map(|weapon| weapon.magazine).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn ammo(&self) -> u32 {
+        self.ammo.get(self.selected).copied().unwrap_or_default()
+    }
+
+    pub fn can_fire(&self, now: f64) -> bool {
+        self.reload_end.is_none() && self.ammo() > 0 && now >= self.next_shot
+    }
+
+    /// Returns the charge of the selected weapon, from `0.` to `1.`, if it is charging.
+    pub fn charge(&self, weapons: &Weapons, now: f64) -> Option<f32> {
+        let charge = weapons.get(self.selected)?.charge.as_ref()?;
+        self.charge_start
+            .map(|charge_start| charge.fraction(now - charge_start))
+    }
+
+    /// Selects another weapon, its reload and charge are interrupted.
+    pub fn select(&mut self, index: usize, weapons: &Weapons) {
+        if index != self.selected && index < weapons.0.len() {
+            self.selected = index;
+            self.reload_end = None;
+            self.charge_start = None;
+        }
+    }
+
+    /// Starts to reload the selected weapon if its magazine is not full.
+    pub fn reload(&mut self, weapons: &Weapons, now: f64) {
+        let Some(weapon) = weapons.get(self.selected) else {
+            return;
+        };
+        if self.reload_end.is_none() && self.ammo() < weapon.magazine {
+            self.reload_end = Some(now + weapon.reload_time as f64);
+            self.charge_start = None;
+        }
+    }
+
+    /// Finishes the reload when its time is over.
+    pub fn update(&mut self, weapons: &Weapons, now: f64) {
+        if self.reload_end.is_some_and(|reload_end| now >= reload_end) {
+            self.reload_end = None;
+            if let (Some(ammo), Some(weapon)) =
+                (self.ammo.get_mut(self.selected), weapons.get(self.selected))
+            {
+                *ammo = weapon.magazine;
+            }
+        }
+    }
+
+    /// Handles the trigger of the selected weapon, returns the charge of the shot if it fires.
+    ///
+    /// Weapons fire while the trigger is held, weapons with a [`Charge`] when it is released.
+    pub fn trigger(&mut self, weapons: &Weapons, pressed: bool, now: f64) -> Option<f32> {
+        let weapon = weapons.get(self.selected)?;
+        let Some(charge) = &weapon.charge else {
+            return (pressed && self.can_fire(now)).then_some(0.);
+        };
+        match self.charge_start {
+            None => {
+                if pressed && self.can_fire(now) {
+                    self.charge_start = Some(now);
+                }
+                None
+            }
+            Some(charge_start) if !pressed => {
+                self.charge_start = None;
+                Some(charge.fraction(now - charge_start))
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// Takes a shot of the selected weapon, an empty magazine is reloaded.
+    pub fn fire(&mut self, weapons: &Weapons, now: f64) {
+        let Some(weapon) = weapons.get(self.selected) else {
+            return;
+        };
+        if let Some(ammo) = self.ammo.get_mut(self.selected) {
+            *ammo = ammo.saturating_sub(1);
+        }
+        self.next_shot = now + 1. / weapon.fire_rate.max(f32::EPSILON) as f64;
+        if self.ammo() == 0 {
+            self.reload(weapons, now);
+        }
+    }
+}
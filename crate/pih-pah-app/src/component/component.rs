@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use bevy::app::{App, PreUpdate, Update};
 use bevy::ecs::entity::Entity;
-use bevy::ecs::event::EventWriter;
+use bevy::ecs::event::{Event, EventWriter};
 use bevy::ecs::query::With;
 use bevy::ecs::system::{Commands, Query, Res};
 use bevy::hierarchy::DespawnRecursiveExt;
@@ -12,6 +12,7 @@ use bevy::time::{Time, Timer};
 use bevy::transform::components::{GlobalTransform, Transform};
 use bevy_xpbd_3d::components::{AngularVelocity, CollisionLayers, LinearVelocity};
 
+use crate::actor::{ExplosionEvent, Explosive, Impact};
 use crate::component::AxisName;
 use crate::lobby::host::DespawnActorEvent;
 use crate::map::SpawnPoint;
@@ -138,11 +139,20 @@ impl Despawn {
     }
 }
 
+/// Sent when an entity with [`Respawn`] has been respawned.
+#[derive(Debug, Event)]
+pub struct RespawnEvent {
+    pub entity: Entity,
+    /// The reason that has caused the respawn.
+    pub reason: DespawnReason,
+}
+
 pub struct ComponentPlugins;
 
 impl Plugin for ComponentPlugins {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreUpdate, (respawn, despawn))
+        app.add_event::<RespawnEvent>()
+            .add_systems(PreUpdate, (respawn, despawn))
             .add_systems(Update, noclip_timer);
     }
 }
@@ -169,14 +179,15 @@ fn noclip_timer(
     }
 }
 
+/// Returns the first reason that is true.
 fn match_reason(
     reason: &mut [DespawnReason],
     global_translation: &Vec3,
     delta_time: &Duration,
-) -> bool {
+) -> Option<DespawnReason> {
     for reason in reason.iter_mut() {
         if match reason {
-            DespawnReason::Forced => true,
+            DespawnReason::Forced | DespawnReason::Killed => true,
             DespawnReason::After(ref mut timer) => timer.update(*delta_time).just_finished(),
             DespawnReason::Less(val, axis) => match axis {
                 AxisName::X => global_translation.x < *val,
@@ -189,11 +200,11 @@ fn match_reason(
                 AxisName::Z => global_translation.z > *val,
             },
         } {
-            return true;
+            return Some(reason.clone());
         }
     }
 
-    false
+    None
 }
 
 /// Processes a [`Entity`] with [`Respawn`] [`Component`]
@@ -204,16 +215,17 @@ fn respawn(
     mut commands: Commands,
     mut respawn_query: Query<(&mut Respawn, &mut Transform, &GlobalTransform, Entity)>,
     mut velocity_query: Query<(&mut LinearVelocity, &mut AngularVelocity), With<Respawn>>,
+    mut respawn_event: EventWriter<RespawnEvent>,
     time: Res<Time>,
 ) {
     for (mut respawn, mut transform, global_transform, entity) in respawn_query.iter_mut() {
-        if !match_reason(
+        let Some(reason) = match_reason(
             &mut respawn.reason,
             &global_transform.translation(),
             &time.delta(),
-        ) {
+        ) else {
             continue;
-        }
+        };
 
         if let NoclipDuration::Timer(val) = respawn.noclip {
             commands
@@ -235,28 +247,47 @@ fn respawn(
 
         respawn
             .reason
-            .retain(|reason| reason != &DespawnReason::Forced);
+            .retain(|reason| !matches!(reason, DespawnReason::Forced | DespawnReason::Killed));
+
+        respawn_event.send(RespawnEvent { entity, reason });
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn despawn(
     mut commands: Commands,
-    mut despawn_query: Query<(&mut Despawn, &GlobalTransform, Option<&LinkId>, Entity)>,
+    mut despawn_query: Query<(
+        &mut Despawn,
+        &GlobalTransform,
+        Option<&LinkId>,
+        Option<(&Explosive, Option<&Impact>)>,
+        Entity,
+    )>,
     mut despawn_actor_event: EventWriter<DespawnActorEvent>,
+    mut explosion_event: EventWriter<ExplosionEvent>,
     time: Res<Time>,
 ) {
-    for (mut respawn, global_transform, id_option, entity) in despawn_query.iter_mut() {
-        if !match_reason(
+    for (mut respawn, global_transform, id_option, explosive, entity) in despawn_query.iter_mut() {
+        if match_reason(
             &mut respawn.reason,
             &global_transform.translation(),
             &time.delta(),
-        ) {
+        )
+        .is_none()
+        {
             continue;
         }
 
         if let Some(id) = id_option {
             despawn_actor_event.send(DespawnActorEvent(id.clone()));
         }
+        if let Some((explosive, impact)) = explosive {
+            explosion_event.send(ExplosionEvent {
+                position: global_transform.translation(),
+                explosive: explosive.clone(),
+                owner: impact.and_then(|impact| impact.owner),
+            });
+        }
 
         commands.entity(entity).despawn_recursive();
     }
@@ -18,6 +18,21 @@ pub enum DespawnReason {
     Less(f32, AxisName),
     /// Specifies that the entity was despawned after timeout.
     After(DespawnTimer),
+    /// Indicates that the character has run out of [`Health`](crate::character::Health). Removed after respawn like [`DespawnReason::Forced`].
+    Killed,
+}
+
+impl DespawnReason {
+    /// Returns the name of the reason kind without its values.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DespawnReason::Forced => "forced",
+            DespawnReason::More(..) => "more",
+            DespawnReason::Less(..) => "less",
+            DespawnReason::After(..) => "after",
+            DespawnReason::Killed => "killed",
+        }
+    }
 }
 
 /// A timer used to despawn an entity after a certain amount of time.
@@ -3,6 +3,7 @@ pub mod character;
 pub mod component;
 pub mod lobby;
 pub mod map;
+pub mod mode;
 pub mod settings;
 pub mod sound;
 pub mod ui;
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use bevy::ecs::system::{Query, Res, ResMut, Resource};
+use bevy::time::Time;
+use bevy_xpbd_3d::components::LinearVelocity;
+use renet::{ClientId, RenetServer};
+
+use super::{Inputs, Lobby, PlayerId};
+
+/// Maximal absolute mouse turn that one input message can carry.
+pub const MAX_TURN: f32 = 500.;
+/// Shots that can be fired in a burst before the fire rate limit applies.
+pub const FIRE_BURST: f32 = 3.;
+/// Shots per second restored to the fire burst.
+pub const FIRE_RATE: f32 = 10.;
+/// Gravity flips that can be done in a burst before the special rate limit applies.
+pub const SPECIAL_BURST: f32 = 2.;
+/// Gravity flips per second restored to the special burst.
+pub const SPECIAL_RATE: f32 = 4.;
+/// Speed that a character can not reach in the simulation without help of a cheat.
+pub const MAX_CHARACTER_SPEED: f32 = 150.;
+/// Number of strikes after which a client is kicked.
+pub const MAX_STRIKES: u32 = 10;
+/// Time in seconds after which one strike is forgiven.
+pub const STRIKE_DECAY: f32 = 30.;
+
+/// A token bucket limiting how often an action can be done.
+#[derive(Debug)]
+struct RateLimit {
+    tokens: f32,
+    capacity: f32,
+    rate: f32,
+    last_update: f32,
+}
+
+impl RateLimit {
+    fn new(capacity: f32, rate: f32, now: f32) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            rate,
+            last_update: now,
+        }
+    }
+
+    /// Takes one token, returns `false` if the bucket is empty.
+    fn take(&mut self, now: f32) -> bool {
+        self.tokens =
+            (self.tokens + (now - self.last_update).max(0.) * self.rate).min(self.capacity);
+        self.last_update = now;
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Validation state of one connected client.
+#[derive(Debug)]
+struct ClientGuard {
+    strikes: u32,
+    last_decay: f32,
+    fire: RateLimit,
+    special: RateLimit,
+    /// Raw pressed states of the buttons in the latest inputs, before the rate limits.
+    fire_pressed: bool,
+    special_pressed: bool,
+    /// The press of the button has been rejected, it stays released until the client lets go.
+    fire_suppressed: bool,
+    special_suppressed: bool,
+}
+
+impl ClientGuard {
+    fn new(now: f32) -> Self {
+        Self {
+            strikes: 0,
+            last_decay: now,
+            fire: RateLimit::new(FIRE_BURST, FIRE_RATE, now),
+            special: RateLimit::new(SPECIAL_BURST, SPECIAL_RATE, now),
+            fire_pressed: false,
+            special_pressed: false,
+            fire_suppressed: false,
+            special_suppressed: false,
+        }
+    }
+}
+
+/// Result of [`AntiCheat::check_inputs`].
+#[derive(Debug, Default)]
+pub struct InputCheck {
+    /// The client has reached [`MAX_STRIKES`] and must be kicked.
+    pub kick: bool,
+    /// A new fire press has been rejected, the client may have predicted its shot.
+    pub fire_rejected: bool,
+}
+
+/// Checks a press of a rate-limited button, a rejected press keeps the button released
+/// until it is let go, so holding it is not seen as new presses.
+///
+/// Returns `true` if a new press has been rejected.
+fn limit_press(
+    pressed: &mut bool,
+    last_pressed: &mut bool,
+    suppressed: &mut bool,
+    limit: &mut RateLimit,
+    now: f32,
+) -> bool {
+    let raw = *pressed;
+    let rejected = raw && !*last_pressed && !limit.take(now);
+    if rejected {
+        *suppressed = true;
+    } else if !raw {
+        *suppressed = false;
+    }
+    *last_pressed = raw;
+    if *suppressed {
+        *pressed = false;
+    }
+    rejected
+}
+
+/// Server side validation of everything the clients send.
+///
+/// Every violation is logged and adds a strike to the client,
+/// strikes are forgiven over time and a client with [`MAX_STRIKES`] strikes must be kicked.
+#[derive(Debug, Default, Resource)]
+pub struct AntiCheat {
+    clients: HashMap<ClientId, ClientGuard>,
+}
+
+impl AntiCheat {
+    /// Forgets everything about a disconnected client.
+    pub fn remove_client(&mut self, client_id: ClientId) {
+        self.clients.remove(&client_id);
+    }
+
+    /// Returns the current number of strikes of the client.
+    pub fn strikes(&self, client_id: ClientId) -> u32 {
+        self.clients
+            .get(&client_id)
+            .map(|guard| guard.strikes)
+            .unwrap_or_default()
+    }
+
+    /// Adds a strike to the client.
+    ///
+    /// Returns `true` if the client has reached [`MAX_STRIKES`] and must be kicked.
+    pub fn strike(&mut self, client_id: ClientId, reason: &str, now: f32) -> bool {
+        let guard = self
+            .clients
+            .entry(client_id)
+            .or_insert_with(|| ClientGuard::new(now));
+
+        let forgiven = ((now - guard.last_decay) / STRIKE_DECAY) as u32;
+        if forgiven > 0 {
+            guard.strikes = guard.strikes.saturating_sub(forgiven);
+            guard.last_decay = now;
+        }
+        if guard.strikes == 0 {
+            guard.last_decay = now;
+        }
+
+        guard.strikes += 1;
+        log::warn!(
+            "Client {} violation: {} (strike {}/{})",
+            client_id,
+            reason,
+            guard.strikes,
+            MAX_STRIKES
+        );
+
+        guard.strikes >= MAX_STRIKES
+    }
+
+    /// Clamps turn rates and rate-limits fire and special of the received inputs.
+    ///
+    /// Only new presses are limited and struck, a rejected press stays released while held.
+    pub fn check_inputs(
+        &mut self,
+        client_id: ClientId,
+        input: &mut Inputs,
+        now: f32,
+    ) -> InputCheck {
+        let mut violations = Vec::new();
+
+        for turn in [&mut input.turn_horizontal, &mut input.turn_vertical] {
+            if !turn.is_finite() {
+                *turn = 0.;
+                violations.push("non finite turn");
+            } else if turn.abs() > MAX_TURN {
+                *turn = turn.clamp(-MAX_TURN, MAX_TURN);
+                violations.push("turn rate exceeded");
+            }
+        }
+
+        let guard = self
+            .clients
+            .entry(client_id)
+            .or_insert_with(|| ClientGuard::new(now));
+
+        let fire_rejected = limit_press(
+            &mut input.fire,
+            &mut guard.fire_pressed,
+            &mut guard.fire_suppressed,
+            &mut guard.fire,
+            now,
+        );
+        if fire_rejected {
+            violations.push("fire rate exceeded");
+        }
+        if limit_press(
+            &mut input.special,
+            &mut guard.special_pressed,
+            &mut guard.special_suppressed,
+            &mut guard.special,
+            now,
+        ) {
+            violations.push("special rate exceeded");
+        }
+
+        let mut kick = false;
+        for reason in violations {
+            kick |= self.strike(client_id, reason, now);
+        }
+        InputCheck {
+            kick,
+            fire_rejected,
+        }
+    }
+}
+
+/// Compares the simulated characters of clients against speed they can not reach,
+/// strikes the owners and slows the characters down.
+pub fn detect_impossible_movement(
+    mut server: ResMut<RenetServer>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    lobby: Res<Lobby>,
+    mut velocity_query: Query<&mut LinearVelocity>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for (player_id, player_data) in lobby.players.iter() {
+        let PlayerId::Client(client_id) = player_id else {
+            continue;
+        };
+        if let Ok(mut linear_velocity) = velocity_query.get_mut(player_data.entity) {
+            if linear_velocity.length() > MAX_CHARACTER_SPEED {
+                linear_velocity.0 = linear_velocity.clamp_length_max(MAX_CHARACTER_SPEED);
+                if anti_cheat.strike(*client_id, "impossible movement speed", now) {
+                    server.disconnect(*client_id);
+                }
+            }
+        }
+    }
+}
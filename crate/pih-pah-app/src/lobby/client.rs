@@ -1,52 +1,123 @@
+use std::collections::HashSet;
 use std::net::UdpSocket;
 use std::time::SystemTime;
 
-use crate::actor::{spawn_projectile_shell, ProjectileShell, UnloadActorsEvent};
-use crate::character::{spawn_character_shell, spawn_tied_camera, TiedCamera};
+use crate::actor::{
+    projectile_shell_trace, spawn_explosion_flash, spawn_predicted_projectile,
+    spawn_projectile_shell, spawn_tracer, PredictedProjectile, ProjectileShell, UnloadActorsEvent,
+};
+use crate::character::{
+    spawn_character_shell, spawn_tied_camera, Dead, TiedCamera, WeaponState, Weapons,
+};
+use crate::component::Despawn;
 use crate::lobby::{LobbyState, PlayerId};
-use crate::map::MapState;
-use crate::world::{input, LinkId, Me};
+use crate::map::{
+    builtin_map_asset, cache_map, cached_map, is_ready, local_map_file, open_map_asset, CustomMap,
+    LocalMapFile, MapFile, MapOverrides, MapState, SpawnPoint,
+};
+use crate::mode::{detach_flags, ControlZone, Flag};
+use crate::util::hash::content_hash;
+use crate::world::{input, LinkId, LinkIndex, Me, PromisedScene};
 use bevy::app::{App, Plugin, Update};
+use bevy::asset::{Assets, Handle};
 use bevy::ecs::entity::Entity;
-use bevy::ecs::event::EventWriter;
-use bevy::ecs::query::With;
-use bevy::ecs::schedule::{Condition, NextState, OnExit};
+use bevy::ecs::event::{Event, EventReader, EventWriter};
+use bevy::ecs::query::{With, Without};
+use bevy::ecs::schedule::{Condition, NextState, OnExit, State};
 use bevy::ecs::system::{Query, Res, ResMut, Resource};
-use bevy::hierarchy::DespawnRecursiveExt;
+use bevy::hierarchy::{DespawnRecursiveExt, Parent};
 use bevy::math::Vec3;
-use bevy::prelude::{in_state, Commands, IntoSystemConfigs, OnEnter};
+use bevy::pbr::StandardMaterial;
+use bevy::prelude::{in_state, resource_exists, Commands, IntoSystemConfigs, OnEnter};
+use bevy::render::color::Color;
+use bevy::render::view::Visibility;
+use bevy::time::Time;
 use bevy::transform::components::Transform;
 use bevy_renet::transport::NetcodeClientPlugin;
 use bevy_renet::RenetClientPlugin;
+use bevy_xpbd_3d::components::{Collider, GravityDirection, LinearVelocity, RigidBody};
+use bevy_xpbd_3d::prelude::SpatialQuery;
 use renet::transport::{ClientAuthentication, NetcodeClientTransport};
-use renet::{ClientId, ConnectionConfig, DefaultChannel, RenetClient};
+use renet::{ClientId, DefaultChannel, RenetClient};
 
 #[derive(Default, Debug, Resource)]
 pub struct OwnId(Option<ClientId>);
 
+/// A map file being received from the server.
+#[derive(Debug, Resource)]
+pub struct MapDownload {
+    map_state: MapState,
+    file: MapFile,
+    data: Vec<u8>,
+    /// Number of times the file has been requested.
+    attempts: u32,
+}
+
+/// Times a map is requested again after a damaged download before the client gives up.
+pub const MAX_MAP_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Largest map file in bytes the client downloads, the size comes from the host.
+pub const MAX_MAP_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Why the client has disconnected while downloading the map, shown on the loading screen.
+#[derive(Debug, Resource)]
+pub struct MapDownloadError(pub String);
+
+/// Time in seconds after which a predicted projectile the server has not confirmed is discarded.
+pub const PREDICTION_TIMEOUT: f32 = 1.;
+
+/// The server's answer to a shot of this client, see [`PredictedProjectile`].
+#[derive(Debug, Event)]
+pub enum ShotEvent {
+    /// The server has spawned a projectile of the shot.
+    Confirmed { id: LinkId, color: Color, shot: u32 },
+    /// The server has dropped the shot.
+    Rejected { shot: u32 },
+}
+
+use super::clock::{request_clock, GameClock};
+use super::compression::{decompress, Codec};
+use super::priority::SnapshotKey;
 use super::{
-    ClientResource, Lobby, PlayerData, PlayerInputs, ServerMessages, TransportDataResource,
-    Username, PROTOCOL_ID,
+    connection_config, ActorKind, ClientMessages, ClientResource, LoadingPlayers, Lobby, MapChunk,
+    MapLoaderState, Notices, Password, PlayerData, PlayerInputs, PlayerView, ServerMessages,
+    TransportDataResource, Username, WorldState, MAP_TRANSFER_CHANNEL, PROTOCOL_ID,
 };
 
 pub struct ClientLobbyPlugins;
 
 impl Plugin for ClientLobbyPlugins {
     fn build(&self, app: &mut App) {
-        app.add_plugins((RenetClientPlugin, NetcodeClientPlugin))
+        app.add_event::<ShotEvent>()
+            .add_plugins((RenetClientPlugin, NetcodeClientPlugin))
             .add_systems(OnEnter(LobbyState::Client), (setup, new_renet_client))
             .add_systems(
                 Update,
-                (client_send_input, client_sync_players)
+                (
+                    client_send_input.after(predict_fire),
+                    request_clock,
+                    predict_fire.run_if(resource_exists::<Weapons>()),
+                    client_sync_players,
+                    reconcile_projectiles.after(client_sync_players),
+                    receive_map,
+                )
                     .after(input)
                     .run_if(in_state(LobbyState::Client).and_then(bevy_renet::client_connected())),
             )
+            .add_systems(
+                Update,
+                load_processing.run_if(
+                    in_state(LobbyState::Client)
+                        .and_then(in_state(MapLoaderState::No))
+                        .and_then(bevy_renet::client_connected()),
+                ),
+            )
             .add_systems(OnExit(LobbyState::Client), teardown);
     }
 }
 
 pub fn new_renet_client(settings: Res<ClientResource>, mut commands: Commands) {
-    commands.insert_resource(RenetClient::new(ConnectionConfig::default()));
+    commands.insert_resource(RenetClient::new(connection_config()));
     let server_addr = settings.address.clone().unwrap().parse().unwrap();
     let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
     let current_time = SystemTime::now()
@@ -56,7 +127,13 @@ pub fn new_renet_client(settings: Res<ClientResource>, mut commands: Commands) {
 
     let username_netcode =
         match Username(settings.username.clone().unwrap().clone()).to_netcode_data() {
-            Ok(bytes) => Some(bytes),
+            Ok(mut bytes) => {
+                if let Some(password) = settings.password.clone() {
+                    Password(password).write_netcode_data(&mut bytes);
+                }
+                Codec::write_netcode_data(&mut bytes);
+                Some(bytes)
+            }
             Err(_) => None,
         };
 
@@ -82,6 +159,29 @@ pub fn client_send_input(
     }
 }
 
+/// Tells the server that the map has been loaded, so it can place the client on it.
+pub fn load_processing(
+    mut client: ResMut<RenetClient>,
+    spawn_point: Res<SpawnPoint>,
+    promised_scene_query: Query<(), With<PromisedScene>>,
+    map_state: Res<State<MapState>>,
+    custom_map: Res<CustomMap>,
+    map_overrides: Res<MapOverrides>,
+    mut next_state_map: ResMut<NextState<MapLoaderState>>,
+) {
+    if *map_state.get() != MapState::Menu && is_ready(&spawn_point, &promised_scene_query) {
+        let local = local_map_file(*map_state.get(), &custom_map, &map_overrides);
+        let message = bincode::serialize(&ClientMessages::MapLoaded {
+            map_state: *map_state.get(),
+            hash: local.map(|local| local.file.hash),
+        })
+        .unwrap();
+        client.send_message(DefaultChannel::ReliableUnordered, message);
+
+        next_state_map.set(MapLoaderState::Yes);
+    }
+}
+
 fn setup(mut commands: Commands) {
     // me
     // let a = Vec3::new(0., 10., 0.);
@@ -91,6 +191,7 @@ fn setup(mut commands: Commands) {
     commands.init_resource::<Lobby>();
     commands.init_resource::<OwnId>();
     commands.init_resource::<TransportDataResource>();
+    commands.init_resource::<GameClock>();
 }
 
 fn teardown(
@@ -108,81 +209,389 @@ fn teardown(
     commands.remove_resource::<Lobby>();
     commands.remove_resource::<OwnId>();
     commands.remove_resource::<TransportDataResource>();
+    commands.remove_resource::<MapDownload>();
+    commands.remove_resource::<GameClock>();
+    commands.remove_resource::<Weapons>();
+    commands.insert_resource(LoadingPlayers::default());
+    commands.insert_resource(MapOverrides::default());
+    commands.insert_resource(Notices::default());
+    commands.remove_resource::<MapDownloadError>();
 
     unload_actors_event.send(UnloadActorsEvent);
 }
 
+/// Switches to the map after making sure this machine has the same file as the host,
+/// another version of the file is downloaded first if it is not cached.
+fn enter_map(
+    commands: &mut Commands,
+    client: &mut RenetClient,
+    custom_map: &mut CustomMap,
+    map_overrides: &mut MapOverrides,
+    next_state_map: &mut NextState<MapState>,
+    map_state: MapState,
+    map_file: Option<MapFile>,
+) {
+    commands.remove_resource::<MapDownload>();
+    let Some(file) = map_file else {
+        next_state_map.set(map_state);
+        return;
+    };
+
+    if let Some(asset_path) = builtin_map_asset(map_state) {
+        match open_map_asset(asset_path) {
+            Ok(local) if local.file.hash == file.hash => {
+                map_overrides.0.remove(&map_state);
+                next_state_map.set(map_state);
+                return;
+            }
+            Ok(_) => log::warn!("Map {} differs from the host version", asset_path),
+            Err(err) => log::warn!("Failed to read map {}: {}", asset_path, err),
+        }
+    }
+
+    if let Some(local) = cached_map(&file) {
+        use_map_file(custom_map, map_overrides, map_state, local);
+        next_state_map.set(map_state);
+    } else if file.size > MAX_MAP_BYTES {
+        let error = format!(
+            "Map {} is too large ({} bytes, at most {})",
+            file.name, file.size, MAX_MAP_BYTES
+        );
+        log::error!("{}, disconnecting", error);
+        commands.insert_resource(MapDownloadError(error));
+        client.disconnect();
+    } else {
+        log::info!("Downloading map {} ({} bytes)", file.name, file.size);
+        let message = bincode::serialize(&ClientMessages::RequestMap {
+            hash: file.hash.clone(),
+        })
+        .unwrap();
+        client.send_message(DefaultChannel::ReliableUnordered, message);
+        commands.insert_resource(MapDownload {
+            map_state,
+            data: Vec::with_capacity(file.size as usize),
+            file,
+            attempts: 1,
+        });
+    }
+}
+
+/// Makes the map load the file received from the host.
+fn use_map_file(
+    custom_map: &mut CustomMap,
+    map_overrides: &mut MapOverrides,
+    map_state: MapState,
+    local: LocalMapFile,
+) {
+    if map_state == MapState::Custom {
+        custom_map.0 = Some(local);
+    } else {
+        map_overrides.0.insert(map_state, local);
+    }
+}
+
+/// Assembles the downloaded map, stores it in the cache and switches to it.
+pub fn receive_map(
+    mut commands: Commands,
+    mut client: ResMut<RenetClient>,
+    mut map_download: Option<ResMut<MapDownload>>,
+    mut custom_map: ResMut<CustomMap>,
+    mut map_overrides: ResMut<MapOverrides>,
+    mut next_state_map: ResMut<NextState<MapState>>,
+) {
+    while let Some(message) = client.receive_message(MAP_TRANSFER_CHANNEL) {
+        let message = match decompress(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                log::error!("Failed to decompress a map chunk: {}", err);
+                continue;
+            }
+        };
+        let chunk: MapChunk = match bincode::deserialize(&message) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                log::error!("Failed to read a map chunk: {}", err);
+                continue;
+            }
+        };
+        let Some(download) = map_download.as_mut() else {
+            continue;
+        };
+        if chunk.hash != download.file.hash || chunk.offset != download.data.len() as u64 {
+            continue;
+        }
+        download.data.extend_from_slice(&chunk.data);
+
+        if download.data.len() as u64 >= download.file.size {
+            if content_hash(&download.data) != download.file.hash {
+                if download.attempts < MAX_MAP_DOWNLOAD_ATTEMPTS {
+                    log::warn!(
+                        "Downloaded map {} is damaged, downloading it again",
+                        download.file.name
+                    );
+                    download.data.clear();
+                    download.attempts += 1;
+                    let message = bincode::serialize(&ClientMessages::RequestMap {
+                        hash: download.file.hash.clone(),
+                    })
+                    .unwrap();
+                    client.send_message(DefaultChannel::ReliableUnordered, message);
+                    continue;
+                }
+                let error = format!(
+                    "Map {} is damaged after {} downloads",
+                    download.file.name, download.attempts
+                );
+                log::error!("{}, disconnecting", error);
+                commands.insert_resource(MapDownloadError(error));
+                commands.remove_resource::<MapDownload>();
+                client.disconnect();
+                return;
+            }
+            commands.remove_resource::<MapDownload>();
+            match cache_map(&download.file, &download.data) {
+                Ok(local) => {
+                    log::info!("Map {} downloaded", download.file.name);
+                    use_map_file(
+                        &mut custom_map,
+                        &mut map_overrides,
+                        download.map_state,
+                        local,
+                    );
+                    next_state_map.set(download.map_state);
+                }
+                Err(err) => log::error!("Failed to cache map {}: {}", download.file.name, err),
+            }
+            return;
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn client_sync_players(
     mut commands: Commands,
     mut client: ResMut<RenetClient>,
     mut transport_data: ResMut<TransportDataResource>,
     mut lobby: ResMut<Lobby>,
+    mut loading_players: ResMut<LoadingPlayers>,
+    mut notices: ResMut<Notices>,
     mut own_id: ResMut<OwnId>,
     mut next_state_map: ResMut<NextState<MapState>>,
-    lincked_obj_query: Query<(Entity, &LinkId)>,
+    mut custom_map: ResMut<CustomMap>,
+    mut map_overrides: ResMut<MapOverrides>,
+    link_index: Res<LinkIndex>,
     mut unload_actors_event: EventWriter<UnloadActorsEvent>,
+    mut shot_event: EventWriter<ShotEvent>,
+    mut clock: ResMut<GameClock>,
+    time: Res<Time>,
+    (mut flag_query, mut zone_query): (
+        Query<(Entity, &mut Flag, Option<&Parent>)>,
+        Query<&mut ControlZone>,
+    ),
 ) {
     // player existence manager
     while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
         let server_message = bincode::deserialize(&message).unwrap();
         match server_message {
-            ServerMessages::InitConnection { id, map_state } => {
-                next_state_map.set(map_state);
+            ServerMessages::InitConnection {
+                id,
+                map_state,
+                map_file,
+                lobby_name,
+                weapons,
+            } => {
+                log::info!("Joined lobby {}", lobby_name);
+                lobby.name = lobby_name;
+                commands.insert_resource(weapons);
+                enter_map(
+                    &mut commands,
+                    &mut client,
+                    &mut custom_map,
+                    &mut map_overrides,
+                    &mut next_state_map,
+                    map_state,
+                    map_file,
+                );
                 if own_id.0.is_some() {
                     panic!("Yeah, I knew it. The server only had to initialize me once. Redo it, you idiot.");
                 } else {
                     *own_id = OwnId(Some(id));
                 }
             }
-            ServerMessages::ChangeMap { map_state } => {
-                next_state_map.set(map_state);
+            ServerMessages::ChangeMap {
+                map_state,
+                map_file,
+            } => {
+                enter_map(
+                    &mut commands,
+                    &mut client,
+                    &mut custom_map,
+                    &mut map_overrides,
+                    &mut next_state_map,
+                    map_state,
+                    map_file,
+                );
                 unload_actors_event.send(UnloadActorsEvent);
             }
             ServerMessages::PlayerConnected {
                 id: player_id,
                 color,
                 username,
+                team,
             } => {
-                let player_entity = commands
-                    .spawn_character_shell(player_id, color, Vec3::ZERO)
-                    .id();
-                if let PlayerId::Client(id) = player_id {
-                    if Some(id) == own_id.0 {
-                        commands.entity(player_entity).insert(Me);
-                        commands.spawn_tied_camera(player_entity);
-                        log::info!("{username} ({id}), welcome.");
-                    } else {
-                        log::info!("Player {} ({}) connected.", username, id);
-                    }
-                } else {
-                    log::info!("Host {} ({:?}).", username, player_id);
-                }
-
-                lobby.players.insert(
+                connect_player(
+                    &mut commands,
+                    &mut lobby,
+                    &own_id,
                     player_id,
                     PlayerData {
-                        entity: player_entity,
+                        entity: Entity::PLACEHOLDER,
                         color,
                         username,
+                        team,
                     },
+                    Vec3::ZERO,
                 );
             }
+            ServerMessages::PlayerTeam { id, team } => {
+                if let Some(player_data) = lobby.players.get_mut(&id) {
+                    player_data.team = Some(team);
+                }
+            }
+            ServerMessages::WorldState { data } => {
+                let data = match decompress(&data) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        log::error!("Failed to decompress the world state: {}", err);
+                        continue;
+                    }
+                };
+                let world_state = match bincode::deserialize(&data) {
+                    Ok(world_state) => world_state,
+                    Err(err) => {
+                        log::error!("Failed to read the world state: {}", err);
+                        continue;
+                    }
+                };
+                apply_world_state(&mut commands, &mut lobby, &own_id, &link_index, world_state);
+            }
             ServerMessages::PlayerDisconnected { id } => {
                 let name = "noname";
 
                 log::info!("Player {} ({:?}) disconnected.", name, id);
                 if let Some(player_data) = lobby.players.remove(&id) {
+                    detach_flags(
+                        &mut commands,
+                        flag_query
+                            .iter()
+                            .filter_map(|(flag, _, parent)| Some((flag, parent?.get()))),
+                        player_data.entity,
+                    );
                     commands.entity(player_data.entity).despawn();
                 }
             }
-            ServerMessages::ProjectileSpawn { id, color } => {
-                commands.spawn_projectile_shell(ProjectileShell { color, id });
+            ServerMessages::PlayerHealth { id, health } => {
+                if let Some(player_data) = lobby.players.get(&id) {
+                    let mut entity = commands.entity(player_data.entity);
+                    entity.insert(health);
+                    if !health.is_dead() {
+                        // the next snapshot shows the respawned character
+                        entity.remove::<Dead>();
+                    }
+                }
+            }
+            ServerMessages::PlayerWeapon { id, state } => {
+                if let Some(player_data) = lobby.players.get(&id) {
+                    commands.entity(player_data.entity).insert(state);
+                }
+            }
+            ServerMessages::PlayerKilled {
+                id,
+                killer,
+                respawn_at,
+            } => {
+                notices.push(lobby.death_message(id, killer), time.elapsed_seconds());
+                if let Some(player_data) = lobby.players.get(&id) {
+                    commands
+                        .entity(player_data.entity)
+                        .insert((Dead { killer, respawn_at }, Visibility::Hidden));
+                }
+            }
+            ServerMessages::Tracer { tracer } => {
+                // the own tracers are predicted
+                if tracer.owner.is_none() || tracer.owner != own_id.0.map(PlayerId::Client) {
+                    commands.spawn_tracer(tracer);
+                }
+            }
+            ServerMessages::Explosion { position, radius } => {
+                commands.spawn_explosion_flash(position, radius);
+            }
+            ServerMessages::ProjectileSpawn {
+                id,
+                color,
+                owner,
+                shot,
+            } => {
+                if owner.is_some() && owner == own_id.0.map(PlayerId::Client) {
+                    shot_event.send(ShotEvent::Confirmed { id, color, shot });
+                } else {
+                    commands.spawn_projectile_shell(ProjectileShell { color, id });
+                }
+            }
+            ServerMessages::ShotRejected { shot } => {
+                shot_event.send(ShotEvent::Rejected { shot });
+            }
+            ServerMessages::LeftRelevance { keys } => {
+                for key in keys {
+                    let entity = match key {
+                        SnapshotKey::Player(player_id) => {
+                            lobby.players.get(&player_id).map(|player| player.entity)
+                        }
+                        // props of the map stay where they have been seen last
+                        SnapshotKey::Actor(link_id @ LinkId::Projectile(_)) => {
+                            link_index.get(&link_id)
+                        }
+                        SnapshotKey::Actor(_) => None,
+                    };
+                    if let Some(entity) = entity {
+                        commands.entity(entity).try_insert(Visibility::Hidden);
+                    }
+                }
             }
             ServerMessages::ActorDespawn { id } => {
-                for (entity, link_id) in lincked_obj_query.iter() {
-                    if link_id == &id {
-                        commands.entity(entity).despawn_recursive();
+                if let Some(entity) = link_index.get(&id) {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            ServerMessages::LoadingPlayers { usernames } => {
+                loading_players.0 = usernames;
+            }
+            ServerMessages::Broadcast { message } => {
+                notices.push(message, time.elapsed_seconds());
+            }
+            ServerMessages::ClockResponse {
+                client_time,
+                server_time,
+            } => {
+                clock.receive_response(client_time, server_time, &time);
+            }
+            ServerMessages::MatchClock { match_end } => {
+                clock.match_end = match_end;
+            }
+            ServerMessages::Scoreboard { scoreboard } => {
+                commands.insert_resource(scoreboard);
+            }
+            ServerMessages::FlagState { team, state } => {
+                for (_, mut flag, _) in flag_query.iter_mut() {
+                    if flag.team == team {
+                        flag.state = state;
+                    }
+                }
+            }
+            ServerMessages::ZoneState { name, state } => {
+                for mut zone in zone_query.iter_mut() {
+                    if zone.name == name {
+                        zone.state = state;
                     }
                 }
             }
@@ -191,7 +600,20 @@ pub fn client_sync_players(
 
     // movements
     while let Some(message) = client.receive_message(DefaultChannel::Unreliable) {
-        transport_data.data = bincode::deserialize(&message).unwrap();
+        let message = match decompress(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                log::error!("Failed to decompress a snapshot: {}", err);
+                continue;
+            }
+        };
+        transport_data.data = match bincode::deserialize(&message) {
+            Ok(data) => data,
+            Err(err) => {
+                log::error!("Failed to read a snapshot: {}", err);
+                continue;
+            }
+        };
         for (player_id, data) in transport_data.data.players.iter() {
             if let Some(player_data) = lobby.players.get(player_id) {
                 let transform = Transform {
@@ -203,21 +625,261 @@ pub fn client_sync_players(
                 commands
                     .entity(player_data.entity)
                     .insert(transform)
-                    .insert(data.player_view);
+                    .insert(data.player_view)
+                    .insert(Visibility::Inherited);
             }
         }
 
         for (link_id, data) in transport_data.data.actors.iter() {
-            for (entity, id) in lincked_obj_query.iter() {
-                if id == link_id {
-                    let transform = Transform {
-                        translation: data.position,
-                        rotation: data.rotation,
-                        ..Default::default()
-                    };
-                    commands.entity(entity).try_insert(transform);
+            if let Some(entity) = link_index.get(link_id) {
+                let transform = Transform {
+                    translation: data.position,
+                    rotation: data.rotation,
+                    ..Default::default()
+                };
+                commands.entity(entity).try_insert(transform);
+                if let LinkId::Projectile(_) = link_id {
+                    commands.entity(entity).try_insert(Visibility::Inherited);
                 }
             }
         }
     }
 }
+
+/// Spawns the character of a player that this client does not know yet.
+fn connect_player(
+    commands: &mut Commands,
+    lobby: &mut Lobby,
+    own_id: &OwnId,
+    player_id: PlayerId,
+    mut player_data: PlayerData,
+    position: Vec3,
+) {
+    if lobby.players.contains_key(&player_id) {
+        return;
+    }
+
+    let player_entity = commands
+        .spawn_character_shell(player_id, player_data.character_color(), position)
+        .id();
+    let username = &player_data.username;
+    if let PlayerId::Client(id) = player_id {
+        if Some(id) == own_id.0 {
+            commands.entity(player_entity).insert(Me);
+            commands.spawn_tied_camera(player_entity);
+            log::info!("{username} ({id}), welcome.");
+        } else {
+            log::info!("Player {} ({}) connected.", username, id);
+        }
+    } else {
+        log::info!("Host {} ({:?}).", username, player_id);
+    }
+
+    player_data.entity = player_entity;
+    lobby.players.insert(player_id, player_data);
+}
+
+/// Replaces the players and projectiles of this client with the ones of the server.
+fn apply_world_state(
+    commands: &mut Commands,
+    lobby: &mut Lobby,
+    own_id: &OwnId,
+    link_index: &LinkIndex,
+    world_state: WorldState,
+) {
+    log::info!(
+        "World state of {}: {} players, {} actors",
+        world_state.map_state,
+        world_state.players.len(),
+        world_state.actors.len()
+    );
+
+    let player_ids: HashSet<PlayerId> =
+        world_state.players.iter().map(|player| player.id).collect();
+    lobby.players.retain(|player_id, player_data| {
+        let exists = player_ids.contains(player_id);
+        if !exists {
+            commands.entity(player_data.entity).despawn_recursive();
+        }
+        exists
+    });
+    for player in world_state.players {
+        let transform = Transform {
+            translation: player.position,
+            rotation: player.rotation,
+            ..Default::default()
+        };
+        connect_player(
+            commands,
+            lobby,
+            own_id,
+            player.id,
+            PlayerData {
+                entity: Entity::PLACEHOLDER,
+                color: player.color,
+                username: player.username,
+                team: player.team,
+            },
+            player.position,
+        );
+        if let Some(player_data) = lobby.players.get(&player.id) {
+            let mut entity = commands.entity(player_data.entity);
+            entity.insert((transform, player.health, player.weapon));
+            match player.dead {
+                Some(dead) => entity.insert((dead, Visibility::Hidden)),
+                None => entity.remove::<Dead>(),
+            };
+        }
+    }
+
+    let link_ids: HashSet<&LinkId> = world_state.actors.iter().map(|actor| &actor.id).collect();
+    for (link_id, entity) in link_index.iter() {
+        if let LinkId::Projectile(_) = link_id {
+            if !link_ids.contains(link_id) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+    for actor in world_state.actors.iter() {
+        let transform = Transform {
+            translation: actor.position,
+            rotation: actor.rotation,
+            ..Default::default()
+        };
+        match (link_index.get(&actor.id), &actor.kind) {
+            (Some(entity), _) => {
+                commands.entity(entity).try_insert(transform);
+            }
+            (None, ActorKind::Projectile { color }) => {
+                commands
+                    .spawn_projectile_shell(ProjectileShell {
+                        color: *color,
+                        id: actor.id.clone(),
+                    })
+                    .insert(transform);
+            }
+            (None, ActorKind::Scene) => {
+                log::warn!("Scene actor {:?} not found", actor.id);
+            }
+        }
+    }
+}
+
+/// Spawns predicted projectiles and hitscan tracers as soon as the player fires,
+/// without waiting for the server.
+///
+/// The weapon state is predicted until the server sends the real one.
+/// Every predicted shot gets the next number, sent to the server in [`Inputs::shot`](super::Inputs::shot).
+#[allow(clippy::too_many_arguments)]
+pub fn predict_fire(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &mut PlayerInputs,
+            &PlayerView,
+            &Transform,
+            &mut WeaponState,
+        ),
+        (With<Me>, Without<Dead>),
+    >,
+    spatial_query: SpatialQuery,
+    own_id: Res<OwnId>,
+    weapons: Res<Weapons>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+    mut shot: Local<u32>,
+) {
+    let Ok((entity, mut input, view, transform, mut state)) = query.get_single_mut() else {
+        return;
+    };
+    let now = clock.now(&time);
+    let charge = state.trigger(&weapons, input.get().fire, now);
+    if charge.is_some() {
+        *shot = shot.wrapping_add(1);
+    }
+    input.set_shot(*shot);
+    let Some(charge) = charge else {
+        return;
+    };
+    if let Some(weapon) = weapons.get(state.selected) {
+        match &weapon.hitscan {
+            Some(hitscan) => {
+                let owner = own_id.0.map(PlayerId::Client);
+                for pellet in hitscan.shot(weapon, entity, transform, view, owner, &spatial_query) {
+                    commands.spawn_tracer(pellet.tracer);
+                }
+            }
+            None => {
+                for projectile in weapon.shot(transform, view, None, charge, *shot) {
+                    commands.spawn_predicted_projectile(projectile, time.elapsed_seconds());
+                }
+            }
+        }
+        state.fire(&weapons, now);
+    }
+}
+
+/// Replaces the oldest predicted projectile of a shot with the confirmed one of the server
+/// and discards predicted projectiles that are rejected or never confirmed.
+///
+/// Shots are matched by their numbers, shots the server has dropped silently just expire.
+pub fn reconcile_projectiles(
+    mut commands: Commands,
+    mut shot_event: EventReader<ShotEvent>,
+    predicted_query: Query<(Entity, &PredictedProjectile, &Handle<StandardMaterial>)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    time: Res<Time>,
+) {
+    let mut predicted: Vec<_> = predicted_query.iter().collect();
+    predicted.sort_by(|a, b| a.1.fired.total_cmp(&b.1.fired));
+
+    for event in shot_event.read() {
+        match event {
+            ShotEvent::Confirmed { id, color, shot } => {
+                let index = predicted
+                    .iter()
+                    .position(|(_, predicted, _)| predicted.shot == *shot);
+                let Some((entity, _, material)) = index.map(|index| predicted.remove(index)) else {
+                    // the prediction has expired or the shot has not been predicted
+                    commands.spawn_projectile_shell(ProjectileShell {
+                        color: *color,
+                        id: id.clone(),
+                    });
+                    continue;
+                };
+                // snapshots move the projectile from now on
+                if let Some(material) = materials.get_mut(material) {
+                    material.base_color = *color;
+                }
+                commands
+                    .entity(entity)
+                    .remove::<(
+                        PredictedProjectile,
+                        RigidBody,
+                        Collider,
+                        LinearVelocity,
+                        GravityDirection,
+                        Despawn,
+                    )>()
+                    .insert((id.clone(), projectile_shell_trace(*color)));
+            }
+            ShotEvent::Rejected { shot } => {
+                predicted.retain(|(entity, predicted, _)| {
+                    let rejected = predicted.shot == *shot;
+                    if rejected {
+                        commands.entity(*entity).despawn_recursive();
+                    }
+                    !rejected
+                });
+            }
+        }
+    }
+
+    let now = time.elapsed_seconds();
+    for (entity, predicted, _) in predicted {
+        if now - predicted.fired > PREDICTION_TIMEOUT {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::ecs::schedule::State;
+use bevy::ecs::system::{Local, Res, ResMut, Resource};
+use bevy::time::Time;
+use renet::{ClientId, DefaultChannel, RenetClient, RenetServer};
+
+use crate::mode::RoundTimer;
+
+use super::{ClientMessages, MapLoaderState, ServerMessages};
+
+/// Seconds between two clock requests of a client.
+pub const CLOCK_SYNC_INTERVAL: f64 = 1.;
+/// Latest clock samples of which the one with the shortest round trip is used.
+pub const CLOCK_SAMPLES: usize = 8;
+/// Change of a timed state in seconds after which it is sent to the clients again.
+pub const CLOCK_TOLERANCE: f64 = 0.1;
+
+/// One exchange of [`ClientMessages::ClockRequest`] and [`ServerMessages::ClockResponse`].
+#[derive(Debug, Clone, Copy)]
+struct ClockSample {
+    rtt: f64,
+    offset: f64,
+}
+
+/// The game clock shared by the host and the clients.
+///
+/// Server time is the elapsed time of the host, clients estimate it NTP-style
+/// from the round trips of clock requests. Timed states are replicated
+/// as absolute server timestamps, so every machine counts them down the same way.
+#[derive(Debug, Default, Resource)]
+pub struct GameClock {
+    /// Server time minus the local elapsed time.
+    offset: f64,
+    samples: VecDeque<ClockSample>,
+    last_request: Option<f64>,
+    /// Server time when the current map ends, `None` if it has no time limit.
+    pub match_end: Option<f64>,
+}
+
+impl GameClock {
+    /// Returns the current server time.
+    pub fn now(&self, time: &Time) -> f64 {
+        time.elapsed_seconds_f64() + self.offset
+    }
+
+    /// Returns seconds until the server timestamp, never negative.
+    pub fn until(&self, time: &Time, timestamp: f64) -> f64 {
+        (timestamp - self.now(time)).max(0.)
+    }
+
+    /// Returns `true` when a client has received at least one clock response.
+    pub fn is_synced(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    /// Adds the round trip of a clock request that has been sent at `client_time`
+    /// and answered at `server_time`, `now` is the local time of the response.
+    fn add_sample(&mut self, client_time: f64, server_time: f64, now: f64) {
+        let rtt = (now - client_time).max(0.);
+        self.samples.push_back(ClockSample {
+            rtt,
+            offset: server_time + rtt / 2. - now,
+        });
+        if self.samples.len() > CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        // the shortest round trip has the most symmetric delays
+        if let Some(best) = self.samples.iter().min_by(|a, b| a.rtt.total_cmp(&b.rtt)) {
+            self.offset = best.offset;
+        }
+    }
+
+    /// Handles a [`ServerMessages::ClockResponse`] on a client.
+    pub fn receive_response(&mut self, client_time: f64, server_time: f64, time: &Time) {
+        let synced = self.is_synced();
+        self.add_sample(client_time, server_time, time.elapsed_seconds_f64());
+        if !synced {
+            log::info!("Clock synced with the server, offset {:.3}s", self.offset);
+        }
+    }
+}
+
+/// Sends clock requests to the server every [`CLOCK_SYNC_INTERVAL`].
+pub fn request_clock(
+    mut client: ResMut<RenetClient>,
+    mut clock: ResMut<GameClock>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    if clock
+        .last_request
+        .is_some_and(|last_request| now - last_request < CLOCK_SYNC_INTERVAL)
+    {
+        return;
+    }
+    clock.last_request = Some(now);
+
+    let message = bincode::serialize(&ClientMessages::ClockRequest { client_time: now }).unwrap();
+    client.send_message(DefaultChannel::ReliableUnordered, message);
+}
+
+/// Updates the end of the current map from the [`RoundTimer`] and sends it to the clients
+/// that do not know it yet.
+pub fn send_match_clock(
+    mut server: ResMut<RenetServer>,
+    mut clock: ResMut<GameClock>,
+    mut synced_clients: Local<HashSet<ClientId>>,
+    round_timer: Res<RoundTimer>,
+    map_loader_state: Res<State<MapLoaderState>>,
+    time: Res<Time>,
+) {
+    // the time of the map does not run while it is loading
+    let match_end = match map_loader_state.get() {
+        MapLoaderState::Yes => round_timer
+            .time_left()
+            .map(|time_left| clock.now(&time) + time_left as f64),
+        MapLoaderState::No => None,
+    };
+    let changed = match (clock.match_end, match_end) {
+        (Some(old), Some(new)) => (old - new).abs() > CLOCK_TOLERANCE,
+        (old, new) => old.is_some() != new.is_some(),
+    };
+    if changed {
+        clock.match_end = match_end;
+        synced_clients.clear();
+    }
+
+    let clients = server.clients_id();
+    synced_clients.retain(|client_id| clients.contains(client_id));
+    for client_id in clients {
+        if synced_clients.insert(client_id) {
+            let message = bincode::serialize(&ServerMessages::MatchClock {
+                match_end: clock.match_end,
+            })
+            .unwrap();
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Display;
+
+use bevy::math::{Quat, Vec3};
+use renet::transport::NETCODE_USER_DATA_BYTES;
+use renet::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::world::LinkId;
+
+use super::{ActorTransportData, PlayerId, PlayerTransportData, PlayerView, CODECS_BYTE};
+
+/// Largest payload a peer can ask to decompress, bigger ones are refused.
+pub const MAX_DECOMPRESSED_BYTES: usize = 1024 * 1024;
+
+/// Compression of a payload, written in its first byte.
+///
+/// LZ4 is used because it compresses and decompresses in pure Rust,
+/// the zstd support of bevy can only decode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+    /// LZ4 with a dictionary of snapshot entries, see [`snapshot_dictionary`].
+    Lz4Dictionary = 2,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::None, Codec::Lz4, Codec::Lz4Dictionary];
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|codec| *codec as u8 == byte)
+    }
+
+    /// Writes the codecs this client can decode into the netcode user data.
+    pub fn write_netcode_data(user_data: &mut [u8; NETCODE_USER_DATA_BYTES]) {
+        user_data[CODECS_BYTE] = Self::ALL
+            .into_iter()
+            .fold(0, |mask, codec| mask | (1 << codec as u8));
+    }
+
+    /// Returns this codec if the client has announced it in its netcode user data,
+    /// otherwise [`Codec::None`].
+    pub fn negotiate(self, user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Self {
+        if user_data[CODECS_BYTE] & (1 << self as u8) != 0 {
+            self
+        } else {
+            Codec::None
+        }
+    }
+}
+
+/// Codecs of the channels of one client, chosen by the server when the client connects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelCodecs {
+    /// Codec of large reliable messages, like the [`WorldState`](super::WorldState).
+    pub reliable: Codec,
+    /// Codec of the map transfer channel.
+    pub map_transfer: Codec,
+    /// Codec of the snapshots on the unreliable channel.
+    pub snapshot: Codec,
+}
+
+#[derive(Debug)]
+pub enum CompressionError {
+    UnknownCodec(u8),
+    TooLarge(usize),
+    Corrupted,
+}
+
+impl Display for CompressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionError::UnknownCodec(byte) => write!(f, "unknown codec {}", byte),
+            CompressionError::TooLarge(size) => write!(f, "payload of {} bytes is too large", size),
+            CompressionError::Corrupted => write!(f, "corrupted payload"),
+        }
+    }
+}
+
+impl std::error::Error for CompressionError {}
+
+lazy_static::lazy_static! {
+    static ref SNAPSHOT_DICTIONARY: Vec<u8> = assemble_snapshot_dictionary();
+}
+
+/// Assembles the dictionary by hand from made-up entries shaped like the ones of snapshots,
+/// it is not trained on captured snapshots. The host and the clients assemble the same one.
+///
+/// Entries are serialized one by one, the order of a serialized
+/// [`TransportData`](super::TransportData) differs between machines.
+fn assemble_snapshot_dictionary() -> Vec<u8> {
+    let player = |position: Vec3| PlayerTransportData {
+        position,
+        rotation: Quat::IDENTITY,
+        player_view: PlayerView::default(),
+    };
+    let actor = |position: Vec3| ActorTransportData {
+        position,
+        rotation: Quat::IDENTITY,
+    };
+
+    let mut dictionary = Vec::new();
+    for count in [1u64, 2, 4, 8] {
+        // lengths of the players and actors maps
+        dictionary.extend(bincode::serialize(&(count, count)).unwrap());
+        dictionary.extend(
+            bincode::serialize(&(PlayerId::HostOrSingle, player(Vec3::new(0., 1., 0.)))).unwrap(),
+        );
+    }
+    for id in 0..8u64 {
+        let position = Vec3::new(id as f32, 1., -(id as f32));
+        dictionary.extend(
+            bincode::serialize(&(PlayerId::Client(ClientId::from_raw(id)), player(position)))
+                .unwrap(),
+        );
+        dictionary.extend(
+            bincode::serialize(&(LinkId::Projectile(id as usize), actor(position))).unwrap(),
+        );
+    }
+    dictionary.extend(
+        bincode::serialize(&(LinkId::Scene("cube".to_string()), actor(Vec3::ZERO))).unwrap(),
+    );
+    dictionary
+}
+
+/// Returns the dictionary of [`Codec::Lz4Dictionary`].
+pub fn snapshot_dictionary() -> &'static [u8] {
+    &SNAPSHOT_DICTIONARY
+}
+
+/// Compresses the payload and prepends its codec,
+/// the payload stays uncompressed if compression does not make it smaller.
+pub fn compress(codec: Codec, payload: &[u8]) -> Vec<u8> {
+    let compressed = match codec {
+        Codec::None => None,
+        Codec::Lz4 => Some(lz4_flex::block::compress_prepend_size(payload)),
+        Codec::Lz4Dictionary => Some(lz4_flex::block::compress_prepend_size_with_dict(
+            payload,
+            snapshot_dictionary(),
+        )),
+    };
+
+    let (codec, data) = match compressed {
+        Some(compressed) if compressed.len() < payload.len() => (codec, compressed),
+        _ => (Codec::None, payload.to_vec()),
+    };
+    let mut message = Vec::with_capacity(data.len() + 1);
+    message.push(codec as u8);
+    message.extend(data);
+    message
+}
+
+/// Decompresses a message made by [`compress`].
+pub fn decompress(message: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let Some((&byte, data)) = message.split_first() else {
+        return Err(CompressionError::Corrupted);
+    };
+    let codec = Codec::from_byte(byte).ok_or(CompressionError::UnknownCodec(byte))?;
+    if codec == Codec::None {
+        return Ok(data.to_vec());
+    }
+
+    // the size is checked before lz4 allocates the buffer for it
+    let size = data
+        .get(..4)
+        .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize)
+        .ok_or(CompressionError::Corrupted)?;
+    if size > MAX_DECOMPRESSED_BYTES {
+        return Err(CompressionError::TooLarge(size));
+    }
+
+    let result = match codec {
+        Codec::Lz4 => lz4_flex::block::decompress_size_prepended(data),
+        _ => lz4_flex::block::decompress_size_prepended_with_dict(data, snapshot_dictionary()),
+    };
+    result.map_err(|_| CompressionError::Corrupted)
+}
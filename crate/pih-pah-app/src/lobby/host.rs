@@ -1,38 +1,188 @@
+use std::collections::{HashMap, HashSet};
 use std::net::UdpSocket;
 use std::time::SystemTime;
 
-use crate::actor::UnloadActorsEvent;
-use crate::character::{fire, spawn_character, spawn_tied_camera, TiedCamera};
+use crate::actor::{ExplosionEvent, ProjectileColor, Tracer, UnloadActorsEvent};
+use crate::character::{
+    fire, spawn_character, spawn_tied_camera, Dead, DeathEvent, Health, TiedCamera, WeaponState,
+    Weapons,
+};
 use crate::component::{DespawnReason, Respawn};
-use crate::lobby::{LobbyState, PlayerData, PlayerId, ServerMessages, Username};
-use crate::map::{is_loaded, MapState, SpawnPoint};
+use crate::lobby::{LobbyState, Password, PlayerData, PlayerId, ServerMessages, Username};
+use crate::map::{
+    asset_root, is_loaded, local_map_file, CustomMap, LocalMapFile, MapOverrides, MapState,
+    SpawnPoint, TeamSpawnPoints,
+};
+use crate::mode::{detach_flags, join_team, team_spawn_point, Flag, GameMode, SwitchTeamEvent};
+use crate::settings::ServerSettings;
 use crate::world::{LinkId, Me};
 use bevy::app::{App, Plugin, Update};
 use bevy::ecs::entity::Entity;
 use bevy::ecs::event::{Event, EventReader, EventWriter};
-use bevy::ecs::query::With;
+use bevy::ecs::query::{Changed, With, Without};
 use bevy::ecs::schedule::{Condition, NextState, OnExit, State};
+use bevy::ecs::system::Resource;
 use bevy::ecs::system::{Query, Res, ResMut};
-use bevy::hierarchy::DespawnRecursiveExt;
+use bevy::hierarchy::{DespawnRecursiveExt, Parent};
 use bevy::log::info;
-use bevy::prelude::{in_state, Color, Commands, IntoSystemConfigs, OnEnter};
+use bevy::math::{Quat, Vec3};
+use bevy::prelude::{in_state, resource_exists, Color, Commands, IntoSystemConfigs, OnEnter};
+use bevy::render::view::Visibility;
+use bevy::time::{Time, Timer, TimerMode};
 use bevy::transform::components::Transform;
 use bevy_renet::transport::NetcodeServerPlugin;
 use bevy_renet::RenetServerPlugin;
-use bevy_xpbd_3d::components::{Position, Rotation};
+use bevy_xpbd_3d::components::{LinearVelocity, Position, RigidBody, Rotation};
 use renet::transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
-use renet::{ConnectionConfig, DefaultChannel, RenetServer, ServerEvent};
+use renet::{ClientId, DefaultChannel, RenetServer, ServerEvent};
 
+use super::anti_cheat::{detect_impossible_movement, AntiCheat};
+use super::clock::{send_match_clock, GameClock};
+use super::compression::{compress, ChannelCodecs};
+use super::map_rotation::MapRotation;
+use super::match_log::{write_match_log, MatchEvent, MatchLog, MatchLogEvent};
+use super::metrics::{collect_metrics, serve_metrics, Metrics};
+use super::priority::{SnapshotItem, SnapshotPriorities};
+use super::rcon::{rcon_update, Rcon};
 use super::{
-    ActorTransportData, ChangeMapLobbyEvent, Character, HostResource, Inputs, Lobby,
-    MapLoaderState, PlayerInputs, PlayerTransportData, PlayerView, TransportDataResource,
-    PROTOCOL_ID,
+    connection_config, ActorKind, ActorState, ActorTransportData, ChangeMapLobbyEvent, Character,
+    ClientMessages, HostResource, Inputs, LoadingPlayers, Lobby, MapChunk, MapLoaderState, Notices,
+    PlayerInputs, PlayerState, PlayerTransportData, PlayerView, WorldState, MAP_CHUNK_SIZE,
+    MAP_TRANSFER_CHANNEL, PROTOCOL_ID,
 };
 
 #[derive(Debug, Event)]
 pub struct DespawnActorEvent(pub LinkId);
 #[derive(Debug, Event)]
-pub struct SpawnProjectileEvent(pub LinkId, pub Color);
+pub struct SpawnProjectileEvent(pub LinkId, pub Color, pub Option<PlayerId>, pub u32);
+#[derive(Debug, Event)]
+pub struct SpawnTracerEvent(pub Tracer);
+
+/// Map loading progress of a connected client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientLoadState {
+    /// The client is loading the map.
+    Loading,
+    /// The client has loaded the map and waits to be placed on it.
+    Loaded,
+    /// The client plays on the map, actors are replicated to it.
+    Ready,
+}
+
+#[derive(Debug)]
+pub struct ClientLoading {
+    pub state: ClientLoadState,
+    pub username: String,
+    /// Compression of the channels of the client, negotiated when it connects.
+    pub codecs: ChannelCodecs,
+}
+
+/// Map loading progress of all connected clients.
+///
+/// Clients are not replicated and do not get a character until they acknowledge
+/// with [`ClientMessages::MapLoaded`] that they have loaded the same map as the host.
+#[derive(Debug, Default, Resource)]
+pub struct ClientsLoadState {
+    /// The map that clients must load.
+    pub map: MapState,
+    /// File of the map, clients must load a file with the same hash.
+    pub map_file: Option<LocalMapFile>,
+    pub clients: HashMap<ClientId, ClientLoading>,
+}
+
+impl ClientsLoadState {
+    /// Returns `true` if the client plays on the current map.
+    pub fn is_ready(&self, client_id: ClientId) -> bool {
+        self.clients
+            .get(&client_id)
+            .map(|client| client.state == ClientLoadState::Ready)
+            .unwrap_or(false)
+    }
+
+    /// Returns all clients that play on the current map.
+    pub fn ready_clients(&self) -> Vec<ClientId> {
+        self.clients
+            .iter()
+            .filter(|(_, client)| client.state == ClientLoadState::Ready)
+            .map(|(client_id, _)| *client_id)
+            .collect()
+    }
+
+    /// Returns sorted usernames of the clients that are not yet on the current map.
+    pub fn loading_usernames(&self) -> Vec<String> {
+        let mut usernames: Vec<String> = self
+            .clients
+            .values()
+            .filter(|client| client.state != ClientLoadState::Ready)
+            .map(|client| client.username.clone())
+            .collect();
+        usernames.sort();
+        usernames
+    }
+}
+
+/// Ready clients that know a state replicated on change, kept in a `Local` of the sending system.
+///
+/// Clients that become ready get the whole state, the others only its changed parts.
+#[derive(Debug, Default)]
+pub struct SyncedClients(HashSet<ClientId>);
+
+impl SyncedClients {
+    /// Marks every ready client as synced, call it once per frame before sending.
+    pub fn sync(&mut self, clients_load_state: &ClientsLoadState) -> SyncTargets {
+        let ready = clients_load_state.ready_clients();
+        self.0.retain(|client_id| ready.contains(client_id));
+        let new = ready
+            .iter()
+            .copied()
+            .filter(|client_id| self.0.insert(*client_id))
+            .collect();
+        SyncTargets { ready, new }
+    }
+}
+
+/// Receivers of the parts of a replicated state in one frame, see [`SyncedClients::sync`].
+#[derive(Debug)]
+pub struct SyncTargets {
+    ready: Vec<ClientId>,
+    new: Vec<ClientId>,
+}
+
+impl SyncTargets {
+    /// Sends a part of the state to every ready client if it has changed,
+    /// otherwise only to the clients that do not know it yet.
+    pub fn send(
+        &self,
+        server: &mut RenetServer,
+        changed: bool,
+        message: impl FnOnce() -> ServerMessages,
+    ) {
+        let clients = if changed { &self.ready } else { &self.new };
+        if clients.is_empty() {
+            return;
+        }
+        let message = bincode::serialize(&message()).unwrap();
+        for client_id in clients {
+            server.send_message(*client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Time between two snapshots sent to the clients, see [`ServerSettings::tick_rate`].
+#[derive(Debug, Resource)]
+pub struct SnapshotTimer(pub Timer);
+
+/// A map file being sent to a client.
+#[derive(Debug)]
+pub struct MapUpload {
+    hash: String,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+/// Map files being sent to clients, see [`ClientMessages::RequestMap`].
+#[derive(Debug, Default, Resource)]
+pub struct MapUploads(HashMap<ClientId, MapUpload>);
 
 pub struct HostLobbyPlugins;
 
@@ -40,6 +190,8 @@ impl Plugin for HostLobbyPlugins {
     fn build(&self, app: &mut App) {
         app.add_event::<DespawnActorEvent>()
             .add_event::<SpawnProjectileEvent>()
+            .add_event::<SpawnTracerEvent>()
+            .add_event::<MatchLogEvent>()
             .add_plugins((RenetServerPlugin, NetcodeServerPlugin))
             .add_systems(OnEnter(LobbyState::Host), setup)
             .add_systems(
@@ -48,7 +200,16 @@ impl Plugin for HostLobbyPlugins {
                     send_change_map,
                     server_sync_actor,
                     spawn_projectile,
+                    send_tracers,
+                    send_explosions,
                     despawn_actor,
+                    send_health,
+                    send_weapons,
+                    send_deaths,
+                    detect_impossible_movement,
+                    sync_loading_players,
+                    upload_maps,
+                    send_match_clock,
                 )
                     .run_if(in_state(LobbyState::Host)),
             )
@@ -58,11 +219,30 @@ impl Plugin for HostLobbyPlugins {
                     .before(fire)
                     .run_if(in_state(LobbyState::Host)),
             )
+            .add_systems(
+                Update,
+                rcon_update.run_if(in_state(LobbyState::Host).and_then(resource_exists::<Rcon>())),
+            )
+            .add_systems(
+                Update,
+                (collect_metrics, serve_metrics)
+                    .run_if(in_state(LobbyState::Host).and_then(resource_exists::<Metrics>())),
+            )
+            .add_systems(
+                Update,
+                write_match_log
+                    .run_if(in_state(LobbyState::Host).and_then(resource_exists::<MatchLog>())),
+            )
             .add_systems(OnExit(LobbyState::Host), teardown)
             .add_systems(
                 Update,
                 load_processing
                     .run_if(in_state(LobbyState::Host).and_then(in_state(MapLoaderState::No))),
+            )
+            .add_systems(
+                Update,
+                place_loaded_clients
+                    .run_if(in_state(LobbyState::Host).and_then(in_state(MapLoaderState::Yes))),
             );
     }
 }
@@ -70,32 +250,149 @@ impl Plugin for HostLobbyPlugins {
 pub fn spawn_projectile(
     mut event_reader: EventReader<SpawnProjectileEvent>,
     mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
 ) {
-    for SpawnProjectileEvent(link_id, color) in event_reader.read() {
+    for SpawnProjectileEvent(link_id, color, owner, shot) in event_reader.read() {
         let message = bincode::serialize(&ServerMessages::ProjectileSpawn {
             id: link_id.clone(),
             color: *color,
+            owner: *owner,
+            shot: *shot,
         })
         .unwrap();
-        server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Sends the tracers of hitscan shots to the clients.
+pub fn send_tracers(
+    mut event_reader: EventReader<SpawnTracerEvent>,
+    mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    for SpawnTracerEvent(tracer) in event_reader.read() {
+        let message = bincode::serialize(&ServerMessages::Tracer {
+            tracer: tracer.clone(),
+        })
+        .unwrap();
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Sends the explosions to the clients, they show them.
+pub fn send_explosions(
+    mut explosion_event: EventReader<ExplosionEvent>,
+    mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    for ExplosionEvent {
+        position,
+        explosive,
+        ..
+    } in explosion_event.read()
+    {
+        let message = bincode::serialize(&ServerMessages::Explosion {
+            position: *position,
+            radius: explosive.radius,
+        })
+        .unwrap();
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
     }
 }
 
 pub fn despawn_actor(
     mut event_reader: EventReader<DespawnActorEvent>,
     mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
 ) {
     for DespawnActorEvent(link_id) in event_reader.read() {
         let message = bincode::serialize(&ServerMessages::ActorDespawn {
             id: link_id.clone(),
         })
         .unwrap();
-        server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Sends the health of characters to the clients when it changes.
+pub fn send_health(
+    mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
+    health_query: Query<(&Character, &Health), Changed<Health>>,
+) {
+    for (character, health) in health_query.iter() {
+        let message = bincode::serialize(&ServerMessages::PlayerHealth {
+            id: character.id,
+            health: *health,
+        })
+        .unwrap();
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Sends the weapon state of characters to the clients when it changes.
+pub fn send_weapons(
+    mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
+    weapon_query: Query<(&Character, &WeaponState), Changed<WeaponState>>,
+) {
+    for (character, state) in weapon_query.iter() {
+        let message = bincode::serialize(&ServerMessages::PlayerWeapon {
+            id: character.id,
+            state: state.clone(),
+        })
+        .unwrap();
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
+    }
+}
+
+/// Announces deaths of characters to the host and the clients.
+pub fn send_deaths(
+    mut death_event: EventReader<DeathEvent>,
+    mut server: ResMut<RenetServer>,
+    mut notices: ResMut<Notices>,
+    clients_load_state: Res<ClientsLoadState>,
+    lobby: Res<Lobby>,
+    time: Res<Time>,
+) {
+    for DeathEvent {
+        victim,
+        killer,
+        respawn_at,
+    } in death_event.read()
+    {
+        notices.push(
+            lobby.death_message(*victim, *killer),
+            time.elapsed_seconds(),
+        );
+
+        let message = bincode::serialize(&ServerMessages::PlayerKilled {
+            id: *victim,
+            killer: *killer,
+            respawn_at: *respawn_at,
+        })
+        .unwrap();
+        // loading clients get the dead characters with the world state when they are placed
+        for client_id in clients_load_state.ready_clients() {
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message.clone());
+        }
     }
 }
 
-pub fn new_renet_server(addr: &str) -> (RenetServer, NetcodeServerTransport) {
-    let server = RenetServer::new(ConnectionConfig::default());
+pub fn new_renet_server(addr: &str, max_clients: usize) -> (RenetServer, NetcodeServerTransport) {
+    let server = RenetServer::new(connection_config());
 
     let public_addr = addr.parse().unwrap();
     let socket = UdpSocket::bind(public_addr).unwrap();
@@ -104,7 +401,7 @@ pub fn new_renet_server(addr: &str) -> (RenetServer, NetcodeServerTransport) {
         .unwrap();
     let server_config = ServerConfig {
         current_time,
-        max_clients: 64,
+        max_clients,
         protocol_id: PROTOCOL_ID,
         public_addresses: vec![public_addr],
         authentication: ServerAuthentication::Unsecure,
@@ -118,27 +415,83 @@ pub fn new_renet_server(addr: &str) -> (RenetServer, NetcodeServerTransport) {
 fn setup(
     mut commands: Commands,
     host_resource: Res<HostResource>,
+    server_settings: Res<ServerSettings>,
+    mut custom_map: ResMut<CustomMap>,
     mut change_map_event: EventWriter<ChangeMapLobbyEvent>,
 ) {
     // resources for server
-    commands.init_resource::<TransportDataResource>();
-    commands.insert_resource(Lobby::default());
+    commands.insert_resource(Lobby {
+        name: server_settings.lobby_name.clone(),
+        ..Default::default()
+    });
+    commands.init_resource::<AntiCheat>();
+    commands.init_resource::<ClientsLoadState>();
+    commands.init_resource::<MapUploads>();
+    commands.init_resource::<SnapshotPriorities>();
+    commands.init_resource::<GameClock>();
+    commands.insert_resource(Weapons::load());
+    commands.insert_resource(SnapshotTimer(Timer::from_seconds(
+        1. / server_settings.tick_rate.max(1) as f32,
+        TimerMode::Repeating,
+    )));
 
     // spanw server
-    let (server, transport) = new_renet_server(host_resource.address.clone().unwrap().as_str());
+    let (server, transport) = new_renet_server(
+        host_resource.address.clone().unwrap().as_str(),
+        server_settings.max_clients,
+    );
     commands.insert_resource(server);
     commands.insert_resource(transport);
 
-    change_map_event.send(ChangeMapLobbyEvent(MapState::ShootingRange));
+    let mut map_rotation = MapRotation::new(server_settings.map_rotation.clone());
+    if !map_rotation.advance(&mut custom_map, &mut change_map_event) {
+        change_map_event.send(ChangeMapLobbyEvent(MapState::ShootingRange));
+    }
+    commands.insert_resource(map_rotation);
+
+    if let Some(password) = server_settings.rcon_password.clone() {
+        match Rcon::bind(server_settings.rcon_port, password) {
+            Ok(rcon) => {
+                log::info!("Rcon listens on port {}", server_settings.rcon_port);
+                commands.insert_resource(rcon);
+            }
+            Err(err) => log::error!("Failed to start rcon: {}", err),
+        }
+    }
+
+    if let Some(port) = server_settings.metrics_port {
+        match Metrics::bind(port) {
+            Ok(metrics) => {
+                log::info!("Metrics are served on port {}", port);
+                commands.insert_resource(metrics);
+            }
+            Err(err) => log::error!("Failed to serve metrics: {}", err),
+        }
+    }
+
+    if server_settings.match_history {
+        match MatchLog::create(
+            server_settings.lobby_name.clone(),
+            host_resource.username.clone().unwrap_or_default(),
+        ) {
+            Ok(match_log) => {
+                log::info!("Match history is written to {:?}", match_log.path());
+                commands.insert_resource(match_log);
+            }
+            Err(err) => log::error!("Failed to create match history: {}", err),
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn load_processing(
     mut commands: Commands,
     spawn_point: Res<SpawnPoint>,
+    team_spawn_points: Res<TeamSpawnPoints>,
     mut lobby_res: ResMut<Lobby>,
     host_resource: Res<HostResource>,
     query: Query<(), With<Me>>,
-    mut character_respawn_query: Query<&mut Respawn, With<Character>>,
+    mut character_respawn_query: Query<(&mut Respawn, &Character)>,
     mut next_state_map: ResMut<NextState<MapLoaderState>>,
 ) {
     info!("LoadProcessing: {:#?}", spawn_point);
@@ -146,54 +499,295 @@ pub fn load_processing(
         if query.get_single().is_err() {
             // spawn host character
             lobby_res.players_seq += 1;
-            let color = generate_player_color(lobby_res.players_seq as u32);
+            let mut player_data = PlayerData {
+                entity: Entity::PLACEHOLDER,
+                color: generate_player_color(lobby_res.players_seq as u32),
+                username: host_resource.username.clone().unwrap(),
+                team: join_team(&lobby_res, host_resource.game_mode),
+            };
+            let spawn_point = team_spawn_point(&spawn_point, &team_spawn_points, player_data.team);
 
-            let player_entity = commands
-                .spawn_character(PlayerId::HostOrSingle, color, spawn_point.random_point())
+            player_data.entity = commands
+                .spawn_character(
+                    PlayerId::HostOrSingle,
+                    player_data.character_color(),
+                    spawn_point.random_point(),
+                )
                 .insert(Me)
                 .id();
-            commands.spawn_tied_camera(player_entity);
-
-            lobby_res.players.insert(
-                PlayerId::HostOrSingle,
-                PlayerData {
-                    entity: player_entity,
-                    color,
-                    username: host_resource.username.clone().unwrap(),
-                },
-            );
+            commands.spawn_tied_camera(player_data.entity);
+
+            lobby_res
+                .players
+                .insert(PlayerId::HostOrSingle, player_data);
         }
 
-        for mut respawn in character_respawn_query.iter_mut() {
-            respawn.replase_spawn_point(spawn_point.clone());
-            respawn.insert_reason(DespawnReason::Forced);
+        // clients are moved onto the map after they have loaded it (see `place_loaded_clients`)
+        for (mut respawn, character) in character_respawn_query.iter_mut() {
+            if character.id == PlayerId::HostOrSingle {
+                let team = lobby_res
+                    .players
+                    .get(&PlayerId::HostOrSingle)
+                    .and_then(|player_data| player_data.team);
+                respawn.replase_spawn_point(team_spawn_point(
+                    &spawn_point,
+                    &team_spawn_points,
+                    team,
+                ));
+                respawn.insert_reason(DespawnReason::Forced);
+            }
         }
 
         next_state_map.set(MapLoaderState::Yes);
     }
 }
 
+/// Places clients that have loaded the current map onto it.
+///
+/// A new client gets its character, a client that already has one is respawned on the map.
+#[allow(clippy::too_many_arguments)]
+pub fn place_loaded_clients(
+    mut commands: Commands,
+    mut server: ResMut<RenetServer>,
+    mut lobby: ResMut<Lobby>,
+    mut clients_load_state: ResMut<ClientsLoadState>,
+    spawn_point: Res<SpawnPoint>,
+    team_spawn_points: Res<TeamSpawnPoints>,
+    game_mode: Res<GameMode>,
+    map_state: Res<State<MapState>>,
+    weapons: Res<Weapons>,
+    mut respawn_query: Query<&mut Respawn, With<Character>>,
+    character_query: Query<(&Transform, &Health, &WeaponState, Option<&Dead>), With<Character>>,
+    actor_query: Query<(&Transform, &LinkId, Option<&ProjectileColor>)>,
+) {
+    if clients_load_state.map != *map_state.get() || !is_loaded(&spawn_point) {
+        return;
+    }
+
+    // positions of the characters spawned now, their transforms do not exist yet
+    let mut spawned = HashMap::new();
+    let mut placed = Vec::new();
+    for (client_id, client) in clients_load_state.clients.iter_mut() {
+        if client.state != ClientLoadState::Loaded {
+            continue;
+        }
+        client.state = ClientLoadState::Ready;
+        placed.push((*client_id, client.codecs.reliable));
+
+        let player_id = PlayerId::Client(*client_id);
+        if let Some(player_data) = lobby.players.get(&player_id) {
+            commands
+                .entity(player_data.entity)
+                .insert((RigidBody::Dynamic, Visibility::Inherited));
+            if let Ok(mut respawn) = respawn_query.get_mut(player_data.entity) {
+                respawn.replase_spawn_point(team_spawn_point(
+                    &spawn_point,
+                    &team_spawn_points,
+                    player_data.team,
+                ));
+                respawn.insert_reason(DespawnReason::Forced);
+            }
+            continue;
+        }
+
+        lobby.players_seq += 1;
+        let mut player_data = PlayerData {
+            entity: Entity::PLACEHOLDER,
+            color: generate_player_color(lobby.players_seq as u32),
+            username: client.username.clone(),
+            team: join_team(&lobby, *game_mode),
+        };
+
+        let position =
+            team_spawn_point(&spawn_point, &team_spawn_points, player_data.team).random_point();
+        player_data.entity = commands
+            .spawn_character(player_id, player_data.character_color(), position)
+            .id();
+        spawned.insert(player_id, position);
+
+        let message = bincode::serialize(&ServerMessages::PlayerConnected {
+            id: player_id,
+            color: player_data.color,
+            username: player_data.username.clone(),
+            team: player_data.team,
+        })
+        .unwrap();
+        server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+
+        lobby.players.insert(player_id, player_data);
+    }
+
+    if placed.is_empty() {
+        return;
+    }
+
+    let world_state = WorldState {
+        map_state: *map_state.get(),
+        players: lobby
+            .players
+            .iter()
+            .map(|(player_id, player_data)| {
+                let (position, rotation, health, weapon, dead) =
+                    match character_query.get(player_data.entity) {
+                        Ok((transform, health, weapon, dead)) => (
+                            transform.translation,
+                            transform.rotation,
+                            *health,
+                            weapon.clone(),
+                            dead.copied(),
+                        ),
+                        Err(_) => (
+                            spawned.get(player_id).copied().unwrap_or_default(),
+                            Quat::IDENTITY,
+                            Health::default(),
+                            WeaponState::new(&weapons),
+                            None,
+                        ),
+                    };
+                PlayerState {
+                    id: *player_id,
+                    color: player_data.color,
+                    username: player_data.username.clone(),
+                    team: player_data.team,
+                    health,
+                    weapon,
+                    dead,
+                    position,
+                    rotation,
+                }
+            })
+            .collect(),
+        actors: actor_query
+            .iter()
+            .map(|(transform, link_id, color)| ActorState {
+                id: link_id.clone(),
+                kind: match color {
+                    Some(ProjectileColor(color)) => ActorKind::Projectile { color: *color },
+                    None => ActorKind::Scene,
+                },
+                position: transform.translation,
+                rotation: transform.rotation,
+            })
+            .collect(),
+    };
+    let data = bincode::serialize(&world_state).unwrap();
+    for (client_id, codec) in placed {
+        let message = bincode::serialize(&ServerMessages::WorldState {
+            data: compress(codec, &data),
+        })
+        .unwrap();
+        server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+    }
+}
+
+/// Keeps [`LoadingPlayers`] of the host and the clients up to date.
+pub fn sync_loading_players(
+    mut server: ResMut<RenetServer>,
+    clients_load_state: Res<ClientsLoadState>,
+    mut loading_players: ResMut<LoadingPlayers>,
+) {
+    let usernames = clients_load_state.loading_usernames();
+    if loading_players.0 != usernames {
+        let message = bincode::serialize(&ServerMessages::LoadingPlayers {
+            usernames: usernames.clone(),
+        })
+        .unwrap();
+        server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+
+        loading_players.0 = usernames;
+    }
+}
+
 pub fn send_change_map(
+    mut commands: Commands,
     mut change_map_event: EventReader<ChangeMapLobbyEvent>,
     mut server: ResMut<RenetServer>,
     mut next_state_map: ResMut<NextState<MapState>>,
     mut unload_actors_event: EventWriter<UnloadActorsEvent>,
+    mut clients_load_state: ResMut<ClientsLoadState>,
+    lobby: Res<Lobby>,
+    custom_map: Res<CustomMap>,
+    map_overrides: Res<MapOverrides>,
 ) {
     for ChangeMapLobbyEvent(state) in change_map_event.read() {
+        let local = local_map_file(*state, &custom_map, &map_overrides);
+        // a change to the same state does not reload the map, clients would wait for it forever
+        let hash =
+            |local: &Option<LocalMapFile>| local.as_ref().map(|local| local.file.hash.clone());
+        if *state == clients_load_state.map && hash(&local) == hash(&clients_load_state.map_file) {
+            log::info!("Map {} is already loaded", state);
+            continue;
+        }
+
         next_state_map.set(*state);
-        let message = bincode::serialize(&ServerMessages::ChangeMap { map_state: *state }).unwrap();
+        let message = bincode::serialize(&ServerMessages::ChangeMap {
+            map_state: *state,
+            map_file: local.as_ref().map(|local| local.file.clone()),
+        })
+        .unwrap();
         server.broadcast_message(DefaultChannel::ReliableOrdered, message);
 
+        // every client must load the new map before it plays again
+        clients_load_state.map = *state;
+        clients_load_state.map_file = local;
+        for client in clients_load_state.clients.values_mut() {
+            client.state = ClientLoadState::Loading;
+        }
+        for (player_id, player_data) in lobby.players.iter() {
+            if player_id.client_id().is_some() {
+                commands
+                    .entity(player_data.entity)
+                    .insert((RigidBody::Static, Visibility::Hidden));
+            }
+        }
+
         unload_actors_event.send(UnloadActorsEvent);
     }
 }
 
+/// Sends parts of requested map files as long as the transfer channel has room for them.
+pub fn upload_maps(
+    mut server: ResMut<RenetServer>,
+    mut map_uploads: ResMut<MapUploads>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    map_uploads.0.retain(|client_id, upload| {
+        let Some(client) = clients_load_state.clients.get(client_id) else {
+            return false;
+        };
+        while upload.offset < upload.data.len() {
+            let end = (upload.offset + MAP_CHUNK_SIZE).min(upload.data.len());
+            let message = bincode::serialize(&MapChunk {
+                hash: upload.hash.clone(),
+                offset: upload.offset as u64,
+                data: upload.data[upload.offset..end].to_vec(),
+            })
+            .unwrap();
+            let message = compress(client.codecs.map_transfer, &message);
+            if !server.can_send_message(*client_id, MAP_TRANSFER_CHANNEL, message.len()) {
+                return true;
+            }
+            server.send_message(*client_id, MAP_TRANSFER_CHANNEL, message);
+            upload.offset = end;
+        }
+
+        log::info!("Map {} sent to client {}", upload.hash, client_id);
+        false
+    });
+}
+
 fn teardown(
     mut commands: Commands,
     tied_camera_query: Query<Entity, With<TiedCamera>>,
     char_query: Query<Entity, With<Character>>,
     mut unload_actors_event: EventWriter<UnloadActorsEvent>,
+    match_log: Option<ResMut<MatchLog>>,
 ) {
+    if let Some(mut match_log) = match_log {
+        match_log.write(MatchEvent::MatchEnd);
+        match_log.flush();
+    }
+
     for entity in tied_camera_query.iter() {
         commands.entity(entity).despawn_recursive();
     }
@@ -201,7 +795,19 @@ fn teardown(
         commands.entity(entity).despawn_recursive();
     }
     commands.remove_resource::<Lobby>();
-    commands.remove_resource::<TransportDataResource>();
+    commands.remove_resource::<AntiCheat>();
+    commands.remove_resource::<ClientsLoadState>();
+    commands.remove_resource::<MapUploads>();
+    commands.remove_resource::<SnapshotTimer>();
+    commands.remove_resource::<SnapshotPriorities>();
+    commands.remove_resource::<GameClock>();
+    commands.remove_resource::<Weapons>();
+    commands.remove_resource::<MapRotation>();
+    commands.remove_resource::<Rcon>();
+    commands.remove_resource::<Metrics>();
+    commands.remove_resource::<MatchLog>();
+    commands.insert_resource(Notices::default());
+    commands.insert_resource(LoadingPlayers::default());
 
     unload_actors_event.send(UnloadActorsEvent);
 }
@@ -219,75 +825,91 @@ pub fn server_update_system(
     mut lobby: ResMut<Lobby>,
     mut server: ResMut<RenetServer>,
     transport: Res<NetcodeServerTransport>,
-    spawn_point: Res<SpawnPoint>,
-    map_state: ResMut<State<MapState>>,
+    mut clients_load_state: ResMut<ClientsLoadState>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    mut map_uploads: ResMut<MapUploads>,
+    server_settings: Res<ServerSettings>,
+    weapons: Res<Weapons>,
+    mut match_log_event: EventWriter<MatchLogEvent>,
+    mut switch_team_event: EventWriter<SwitchTeamEvent>,
+    time: Res<Time>,
 
     mut input_query: Query<&mut PlayerInputs>,
+    flag_query: Query<(Entity, &Parent), With<Flag>>,
 ) {
+    let now = time.elapsed_seconds();
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 log::info!("Player {} connected.", client_id);
 
-                // TODO remove
+                let data = transport.user_data(*client_id).unwrap();
+                if let Some(password) = server_settings.password.clone() {
+                    if !Password(password).matches_user_data(&data) {
+                        log::warn!("Player {} sent a wrong password.", client_id);
+                        server.disconnect(*client_id);
+                        continue;
+                    }
+                }
+
                 let message = bincode::serialize(&ServerMessages::InitConnection {
                     id: *client_id,
-                    map_state: *map_state.get(),
+                    map_state: clients_load_state.map,
+                    map_file: clients_load_state
+                        .map_file
+                        .as_ref()
+                        .map(|local| local.file.clone()),
+                    lobby_name: lobby.name.clone(),
+                    weapons: weapons.clone(),
                 })
                 .unwrap();
+                // players and actors are sent in the world state when the client is placed on the map
                 server.send_message(*client_id, DefaultChannel::ReliableOrdered, message);
 
-                lobby.players_seq += 1;
-                let color = generate_player_color(lobby.players_seq as u32);
-
-                // Spawn player cube
-                let player_entity = commands
-                    .spawn_character(
-                        PlayerId::Client(*client_id),
-                        color,
-                        spawn_point.random_point(),
-                    )
-                    .id();
-
-                // We could send an InitState with all the players id and positions for the multiplayer
-                // but this is easier to do.
-                for (player_id, player_data) in &lobby.players {
-                    let message = bincode::serialize(&ServerMessages::PlayerConnected {
-                        id: *player_id,
-                        color: player_data.color,
-                        username: player_data.username.clone(),
-                    })
-                    .unwrap();
-                    server.send_message(*client_id, DefaultChannel::ReliableOrdered, message);
-                }
-
-                let data = transport.user_data(*client_id).unwrap();
                 let username = match Username::from_user_data(&data) {
                     Ok(name) => name,
                     Err(_) => "@corapted@".to_string(),
                 };
-                // let username = "noname".to_string();
-
-                lobby.players.insert(
-                    PlayerId::Client(*client_id),
-                    PlayerData {
-                        entity: player_entity,
-                        color,
-                        username: username.clone(),
+
+                match_log_event.send(MatchLogEvent(MatchEvent::PlayerConnected {
+                    client_id: client_id.raw(),
+                    username: username.clone(),
+                }));
+
+                // the character is spawned after the client has loaded the map
+                let codecs = ChannelCodecs {
+                    reliable: server_settings.reliable_compression.negotiate(&data),
+                    map_transfer: server_settings.map_transfer_compression.negotiate(&data),
+                    snapshot: server_settings.snapshot_compression.negotiate(&data),
+                };
+                log::info!("Player {} compression: {:?}", client_id, codecs);
+
+                clients_load_state.clients.insert(
+                    *client_id,
+                    ClientLoading {
+                        state: ClientLoadState::Loading,
+                        username,
+                        codecs,
                     },
                 );
-
-                let message = bincode::serialize(&ServerMessages::PlayerConnected {
-                    id: PlayerId::Client(*client_id),
-                    color,
-                    username,
-                })
-                .unwrap();
-                server.broadcast_message(DefaultChannel::ReliableOrdered, message);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 log::info!("Player {} disconnected: {}", client_id, reason);
+                anti_cheat.remove_client(*client_id);
+                if let Some(client) = clients_load_state.clients.remove(client_id) {
+                    match_log_event.send(MatchLogEvent(MatchEvent::PlayerDisconnected {
+                        client_id: client_id.raw(),
+                        username: client.username,
+                        reason: reason.to_string(),
+                    }));
+                }
+                map_uploads.0.remove(client_id);
                 if let Some(player_data) = lobby.players.remove(&PlayerId::Client(*client_id)) {
+                    detach_flags(
+                        &mut commands,
+                        flag_query.iter().map(|(flag, parent)| (flag, parent.get())),
+                        player_data.entity,
+                    );
                     commands.entity(player_data.entity).despawn();
                 }
 
@@ -301,10 +923,108 @@ pub fn server_update_system(
     }
 
     for client_id in server.clients_id().into_iter() {
+        while let Some(message) =
+            server.receive_message(client_id, DefaultChannel::ReliableUnordered)
+        {
+            match bincode::deserialize(&message) {
+                Ok(ClientMessages::MapLoaded { map_state, hash }) => {
+                    if map_state != clients_load_state.map {
+                        continue;
+                    }
+                    let expected_hash = clients_load_state
+                        .map_file
+                        .as_ref()
+                        .map(|local| &local.file.hash);
+                    if hash.as_ref() != expected_hash {
+                        // the client plays on another version of the map, it is refused
+                        log::warn!(
+                            "Client {} loaded map {} with hash {:?} instead of {:?}",
+                            client_id,
+                            map_state,
+                            hash,
+                            expected_hash
+                        );
+                        server.disconnect(client_id);
+                        break;
+                    }
+                    if let Some(client) = clients_load_state.clients.get_mut(&client_id) {
+                        if client.state == ClientLoadState::Loading {
+                            client.state = ClientLoadState::Loaded;
+                        }
+                    }
+                }
+                Ok(ClientMessages::ClockRequest { client_time }) => {
+                    let message = bincode::serialize(&ServerMessages::ClockResponse {
+                        client_time,
+                        server_time: time.elapsed_seconds_f64(),
+                    })
+                    .unwrap();
+                    server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+                }
+                Ok(ClientMessages::SwitchTeam) => {
+                    switch_team_event.send(SwitchTeamEvent(PlayerId::Client(client_id)));
+                }
+                Ok(ClientMessages::RequestMap { hash }) => {
+                    match clients_load_state.map_file.as_ref() {
+                        Some(local) if local.file.hash == hash => {
+                            match std::fs::read(asset_root().join(&local.asset_path)) {
+                                Ok(data) => {
+                                    log::info!("Sending map {} to client {}", hash, client_id);
+                                    map_uploads.0.insert(
+                                        client_id,
+                                        MapUpload {
+                                            hash,
+                                            data,
+                                            offset: 0,
+                                        },
+                                    );
+                                }
+                                Err(err) => {
+                                    log::error!("Failed to read map {}: {}", local.asset_path, err)
+                                }
+                            }
+                        }
+                        _ => log::warn!("Client {} requested unknown map {}", client_id, hash),
+                    }
+                }
+                Err(_) => {
+                    if anti_cheat.strike(client_id, "malformed message", now) {
+                        server.disconnect(client_id);
+                        break;
+                    }
+                }
+            }
+        }
+
+        // inputs of loading clients are dropped, they do not play
+        let is_ready = clients_load_state.is_ready(client_id);
         let mut first = true;
         while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
         {
-            let input: Inputs = bincode::deserialize(&message).unwrap();
+            let mut input: Inputs = match bincode::deserialize(&message) {
+                Ok(input) => input,
+                Err(_) => {
+                    if anti_cheat.strike(client_id, "malformed input", now) {
+                        server.disconnect(client_id);
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let check = anti_cheat.check_inputs(client_id, &mut input, now);
+            if check.kick {
+                server.disconnect(client_id);
+                break;
+            }
+            if check.fire_rejected {
+                // the client has already spawned a predicted projectile
+                let message =
+                    bincode::serialize(&ServerMessages::ShotRejected { shot: input.shot }).unwrap();
+                server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+            }
+            if !is_ready {
+                continue;
+            }
             if let Some(player_data) = lobby.players.get(&PlayerId::Client(client_id)) {
                 if let Ok(mut player_input) = input_query.get_mut(player_data.entity) {
                     if first {
@@ -321,38 +1041,96 @@ pub fn server_update_system(
     }
 }
 
+/// Sends every ready client a snapshot of the players and actors within its relevance radius,
+/// the snapshot is limited by the per-client budget, see [`SnapshotPriorities`].
+#[allow(clippy::too_many_arguments)]
 pub fn server_sync_actor(
     mut server: ResMut<RenetServer>,
-    // TODO a nahooya tut resours, daun
-    mut data: ResMut<TransportDataResource>,
-    character_query: Query<(&Position, &Rotation, &PlayerView, &Character)>,
-    moveble_actor_query: Query<(&Transform, &LinkId)>,
+    clients_load_state: Res<ClientsLoadState>,
+    server_settings: Res<ServerSettings>,
+    mut snapshot_timer: ResMut<SnapshotTimer>,
+    mut snapshot_priorities: ResMut<SnapshotPriorities>,
+    mut metrics: Option<ResMut<Metrics>>,
+    time: Res<Time>,
+    // dead characters are hidden until they respawn
+    character_query: Query<
+        (
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            &PlayerView,
+            &Character,
+        ),
+        Without<Dead>,
+    >,
+    moveble_actor_query: Query<(&Transform, &LinkId, Option<&LinearVelocity>)>,
 ) {
-    let data = &mut data.data;
-    for (position, rotation, view_direction, character) in character_query.iter() {
-        data.players.insert(
-            character.id,
-            PlayerTransportData {
-                position: position.0,
-                rotation: rotation.0,
-                player_view: *view_direction,
-            },
-        );
+    if !snapshot_timer.0.tick(time.delta()).just_finished() {
+        return;
     }
 
-    for (transform, link_id) in moveble_actor_query.iter() {
-        data.actors.insert(
-            link_id.clone(),
-            ActorTransportData {
-                position: transform.translation,
-                rotation: transform.rotation,
-            },
-        );
-    }
+    let players = character_query.iter().map(
+        |(position, rotation, velocity, view_direction, character)| {
+            (
+                SnapshotItem::Player(
+                    character.id,
+                    PlayerTransportData {
+                        position: position.0,
+                        rotation: rotation.0,
+                        player_view: *view_direction,
+                    },
+                ),
+                velocity.0,
+            )
+        },
+    );
+    let actors = moveble_actor_query
+        .iter()
+        .map(|(transform, link_id, velocity)| {
+            (
+                SnapshotItem::Actor(
+                    link_id.clone(),
+                    ActorTransportData {
+                        position: transform.translation,
+                        rotation: transform.rotation,
+                    },
+                ),
+                velocity.map_or(Vec3::ZERO, |velocity| velocity.0),
+            )
+        });
+    let items: Vec<(SnapshotItem, Vec3)> = players.chain(actors).collect();
 
-    let sync_message = bincode::serialize(&data).unwrap();
-    server.broadcast_message(DefaultChannel::Unreliable, sync_message);
+    let delta = snapshot_timer.0.duration().as_secs_f32();
+    let budget = (server_settings.snapshot_bandwidth / server_settings.tick_rate.max(1)) as usize;
+    let radius_squared = server_settings.relevance_radius.powi(2);
+    let ready_clients = clients_load_state.ready_clients();
+    for client_id in ready_clients.iter().copied() {
+        // a client without a character gets everything
+        let viewer = items.iter().find_map(|(item, _)| match item {
+            SnapshotItem::Player(player_id, data) if *player_id == PlayerId::Client(client_id) => {
+                Some(data.position)
+            }
+            _ => None,
+        });
+        let relevant = items.iter().filter(|(item, _)| {
+            viewer.map_or(true, |viewer| {
+                viewer.distance_squared(item.position()) <= radius_squared
+            })
+        });
 
-    data.players.clear();
-    data.actors.clear();
+        let (data, left) = snapshot_priorities.snapshot(client_id, viewer, relevant, delta, budget);
+        if !left.is_empty() {
+            let message =
+                bincode::serialize(&ServerMessages::LeftRelevance { keys: left }).unwrap();
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+        }
+
+        let codec = clients_load_state.clients[&client_id].codecs.snapshot;
+        let sync_message = compress(codec, &bincode::serialize(&data).unwrap());
+        if let Some(metrics) = metrics.as_mut() {
+            metrics.record_snapshot(sync_message.len());
+        }
+        server.send_message(client_id, DefaultChannel::Unreliable, sync_message);
+    }
+    snapshot_priorities.retain_clients(&ready_clients);
 }
@@ -1,5 +1,10 @@
+use crate::actor::Tracer;
+use crate::character::{Dead, Health, WeaponState, Weapons};
+use crate::lobby::priority::SnapshotKey;
 use crate::lobby::single::SingleLobbyPlugins;
-use crate::map::MapState;
+use crate::map::{MapFile, MapState};
+use crate::mode::{FlagState, GameMode, Scoreboard, Team, ZoneState};
+use crate::util::hash::content_hash;
 use crate::world::LinkId;
 use bevy::app::{App, Plugin};
 use bevy::ecs::event::Event;
@@ -7,14 +12,38 @@ use bevy::math::{Quat, Vec3};
 use bevy::prelude::{Color, Component, Entity, Resource, States};
 use bevy::reflect::Reflect;
 use renet::transport::NETCODE_USER_DATA_BYTES;
-use renet::ClientId;
+use renet::{ChannelConfig, ClientId, ConnectionConfig, DefaultChannel, SendType};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 
 use super::client::ClientLobbyPlugins;
 use super::host::HostLobbyPlugins;
 
-pub const PROTOCOL_ID: u64 = 7;
+pub const PROTOCOL_ID: u64 = 20;
+
+/// Server channel for map files ([`MapChunk`]), big transfers do not stall the game messages.
+pub const MAP_TRANSFER_CHANNEL: u8 = 3;
+/// Size of the map file part sent in one [`MapChunk`].
+pub const MAP_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Returns the channels configuration shared by the host and the clients.
+pub fn connection_config() -> ConnectionConfig {
+    let mut server_channels_config = DefaultChannel::config();
+    server_channels_config.push(ChannelConfig {
+        channel_id: MAP_TRANSFER_CHANNEL,
+        max_memory_usage_bytes: 8 * MAP_CHUNK_SIZE,
+        send_type: SendType::ReliableOrdered {
+            resend_time: Duration::from_millis(300),
+        },
+    });
+
+    ConnectionConfig {
+        server_channels_config,
+        client_channels_config: DefaultChannel::config(),
+        ..Default::default()
+    }
+}
 
 /// An enumeration representing the states of a lobby system.
 ///
@@ -48,17 +77,25 @@ pub enum ServerMessages {
     ///
     /// * `id` - Unique identifier for the connecting client.
     /// * `map_state` - Initial state of the client's map.
+    /// * `map_file` - The glb file of the map, clients load the same version of it.
+    /// * `lobby_name` - Name of the lobby.
+    /// * `weapons` - Weapons of the lobby, clients predict shots with them.
     InitConnection {
         id: ClientId,
         map_state: MapState,
+        map_file: Option<MapFile>,
+        lobby_name: String,
+        weapons: Weapons,
     },
     /// Sent to notify a change in the map's state.
     ///
     /// # Fields
     ///
     /// * `map_state` - The new state of the map.
+    /// * `map_file` - The glb file of the map, clients load the same version of it.
     ChangeMap {
         map_state: MapState,
+        map_file: Option<MapFile>,
     },
     /// Indicates that a player has connected to the server.
     ///
@@ -67,10 +104,12 @@ pub enum ServerMessages {
     /// * `id` - Unique identifier for the player.
     /// * `color` - The color assigned to the player.
     /// * `username` - The player's chosen username.
+    /// * `team` - Team of the player in team modes.
     PlayerConnected {
         id: PlayerId,
         color: Color,
         username: String,
+        team: Option<Team>,
     },
     /// Indicates that a player has disconnected from the server.
     ///
@@ -80,13 +119,212 @@ pub enum ServerMessages {
     PlayerDisconnected {
         id: PlayerId,
     },
+    /// Sent when a player has switched to the other team.
+    ///
+    /// # Fields
+    ///
+    /// * `id` - Unique identifier for the player.
+    /// * `team` - The new team of the player.
+    PlayerTeam {
+        id: PlayerId,
+        team: Team,
+    },
+    /// Sent when the health of a character has changed, by damage or respawn.
+    ///
+    /// # Fields
+    ///
+    /// * `id` - Unique identifier for the player.
+    /// * `health` - The new health of the character.
+    PlayerHealth {
+        id: PlayerId,
+        health: Health,
+    },
+    /// Sent when a character has switched, fired or reloaded its weapon.
+    ///
+    /// # Fields
+    ///
+    /// * `id` - Unique identifier for the player.
+    /// * `state` - The selected weapon and the ammo of the character.
+    PlayerWeapon {
+        id: PlayerId,
+        state: WeaponState,
+    },
+    /// Indicates that a character has died.
+    ///
+    /// # Fields
+    ///
+    /// * `id` - Unique identifier for the player who has died.
+    /// * `killer` - The player whose projectile has killed it.
+    /// * `respawn_at` - Server timestamp of the respawn, see [`GameClock`](super::clock::GameClock).
+    PlayerKilled {
+        id: PlayerId,
+        killer: Option<PlayerId>,
+        respawn_at: f64,
+    },
+    /// Indicates that a projectile has been fired.
+    ///
+    /// # Fields
+    ///
+    /// * `id` - Link of the projectile.
+    /// * `color` - Color of the projectile.
+    /// * `owner` - The player that has fired it, its client replaces its predicted projectile.
+    /// * `shot` - Number of the shot of the owner's client, see [`Inputs::shot`].
     ProjectileSpawn {
         id: LinkId,
         color: Color,
+        owner: Option<PlayerId>,
+        shot: u32,
     },
     ActorDespawn {
         id: LinkId,
     },
+    /// Sent when a character has fired a hitscan weapon, one message per pellet.
+    ///
+    /// # Fields
+    ///
+    /// * `tracer` - The path of the shot, its owner has already shown it.
+    Tracer {
+        tracer: Tracer,
+    },
+    /// Sent when an explosive projectile has exploded.
+    ///
+    /// # Fields
+    ///
+    /// * `position` - The center of the explosion.
+    /// * `radius` - The radius the explosion reaches.
+    Explosion {
+        position: Vec3,
+        radius: f32,
+    },
+    /// Sent to a client whose shot has been dropped by the fire rate limit.
+    ///
+    /// # Fields
+    ///
+    /// * `shot` - Number of the dropped shot, the client discards its predicted projectiles.
+    ShotRejected {
+        shot: u32,
+    },
+    /// Sent when characters or actors have left the relevance radius of the client,
+    /// snapshots do not update them until they come back.
+    ///
+    /// # Fields
+    ///
+    /// * `keys` - The characters and actors, the client hides them.
+    LeftRelevance {
+        keys: Vec<SnapshotKey>,
+    },
+    /// Lists the players that are still loading the current map.
+    ///
+    /// # Fields
+    ///
+    /// * `usernames` - Usernames of the loading players, empty when everyone is ready.
+    LoadingPlayers {
+        usernames: Vec<String>,
+    },
+    /// A message from the server operator shown to every player.
+    ///
+    /// # Fields
+    ///
+    /// * `message` - Text of the message.
+    Broadcast {
+        message: String,
+    },
+    /// Answers a [`ClientMessages::ClockRequest`], see [`GameClock`](super::clock::GameClock).
+    ///
+    /// # Fields
+    ///
+    /// * `client_time` - Time of the client sent in the request.
+    /// * `server_time` - Time of the server when the request has been received.
+    ClockResponse {
+        client_time: f64,
+        server_time: f64,
+    },
+    /// The whole world, sent to a client when it is placed on the map.
+    ///
+    /// # Fields
+    ///
+    /// * `data` - Serialized [`WorldState`], compressed with the reliable codec of the client.
+    WorldState {
+        data: Vec<u8>,
+    },
+    /// Tells the server time when the current map ends.
+    ///
+    /// # Fields
+    ///
+    /// * `match_end` - Server timestamp of the end, `None` if the map has no time limit.
+    MatchClock {
+        match_end: Option<f64>,
+    },
+    /// Scores of the current round, sent whenever they change.
+    ///
+    /// # Fields
+    ///
+    /// * `scoreboard` - The whole scoreboard of the round.
+    Scoreboard {
+        scoreboard: Scoreboard,
+    },
+    /// Sent when a flag of capture the flag has been picked up, dropped or returned.
+    ///
+    /// # Fields
+    ///
+    /// * `team` - Team of the flag.
+    /// * `state` - Where the flag is, a carried flag follows its carrier.
+    FlagState {
+        team: Team,
+        state: FlagState,
+    },
+    /// Sent when a control zone of king of the hill has been taken, contested or left.
+    ///
+    /// # Fields
+    ///
+    /// * `name` - Name of the zone from its name tag.
+    /// * `state` - Who is in the zone.
+    ZoneState {
+        name: String,
+        state: ZoneState,
+    },
+}
+
+/// Represents different types of messages that a client can send.
+///
+/// Inputs are sent separately on [`DefaultChannel::ReliableOrdered`](renet::DefaultChannel::ReliableOrdered),
+/// these messages go over [`DefaultChannel::ReliableUnordered`](renet::DefaultChannel::ReliableUnordered).
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessages {
+    /// Sent when the client has finished loading a map.
+    ///
+    /// # Fields
+    ///
+    /// * `map_state` - The map that has been loaded.
+    /// * `hash` - Hash of the loaded [`MapFile`], `None` for maps without a file.
+    MapLoaded {
+        map_state: MapState,
+        hash: Option<String>,
+    },
+    /// Asks the server to send a map file the client does not have.
+    ///
+    /// # Fields
+    ///
+    /// * `hash` - Hash of the [`MapFile`].
+    RequestMap { hash: String },
+    /// Asks the server for its time, see [`GameClock`](super::clock::GameClock).
+    ///
+    /// # Fields
+    ///
+    /// * `client_time` - Time of the client when the request has been sent.
+    ClockRequest { client_time: f64 },
+    /// Asks the server to move the player of the client to the other team.
+    SwitchTeam,
+}
+
+/// A part of a map file, sent on [`MAP_TRANSFER_CHANNEL`] after [`ClientMessages::RequestMap`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MapChunk {
+    /// Hash of the [`MapFile`].
+    pub hash: String,
+    /// Position of the part in the file.
+    pub offset: u64,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
@@ -96,6 +334,14 @@ pub enum MapLoaderState {
     No,
 }
 
+/// Bytes of the netcode user data taken by the hash of [`Password`].
+const PASSWORD_HASH_BYTES: usize = 64;
+/// Position of the mask of the [`Codec`](super::compression::Codec)s a client can decode
+/// in the netcode user data, right before the hash of [`Password`].
+pub const CODECS_BYTE: usize = NETCODE_USER_DATA_BYTES - PASSWORD_HASH_BYTES - 1;
+/// Maximal length of [`Username`] in bytes.
+const USERNAME_BYTES: usize = CODECS_BYTE - 8;
+
 #[derive(Resource)]
 pub struct Username(pub String);
 
@@ -110,7 +356,7 @@ impl Username {
         &self,
     ) -> Result<[u8; NETCODE_USER_DATA_BYTES], Box<dyn std::error::Error>> {
         let mut data = [0u8; NETCODE_USER_DATA_BYTES];
-        if self.0.len() > NETCODE_USER_DATA_BYTES - 8 {
+        if self.0.len() > USERNAME_BYTES {
             let err = Err(From::from("Your username to long"));
             log::error!("{:?}", err);
             return err;
@@ -127,7 +373,7 @@ impl Username {
         let mut buffer = [0u8; 8];
         buffer.copy_from_slice(&user_data[0..8]);
         let mut len = u64::from_le_bytes(buffer) as usize;
-        len = len.min(NETCODE_USER_DATA_BYTES - 8);
+        len = len.min(USERNAME_BYTES);
         let data = user_data[8..len + 8].to_vec();
         let username = String::from_utf8(data)?;
 
@@ -135,24 +381,128 @@ impl Username {
     }
 }
 
+/// Password of a lobby.
+///
+/// Clients send its hash at the end of the netcode user data, after the [`Username`].
+pub struct Password(pub String);
+
+impl Password {
+    /// Writes the password hash into the user data made by [`Username::to_netcode_data`].
+    pub fn write_netcode_data(&self, user_data: &mut [u8; NETCODE_USER_DATA_BYTES]) {
+        user_data[NETCODE_USER_DATA_BYTES - PASSWORD_HASH_BYTES..]
+            .copy_from_slice(content_hash(self.0.as_bytes()).as_bytes());
+    }
+
+    /// Returns `true` if the client has sent the hash of this password.
+    pub fn matches_user_data(&self, user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> bool {
+        user_data[NETCODE_USER_DATA_BYTES - PASSWORD_HASH_BYTES..]
+            == *content_hash(self.0.as_bytes()).as_bytes()
+    }
+}
+
 #[derive(Debug, Default, Resource)]
 pub struct ClientResource {
     pub address: Option<String>,
     pub username: Option<String>,
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Default, Resource)]
 pub struct HostResource {
     pub address: Option<String>,
     pub username: Option<String>,
+    pub game_mode: GameMode,
+    pub friendly_fire: bool,
+}
+
+/// Time in seconds a [`Notice`] is shown.
+pub const NOTICE_LIFETIME: f32 = 8.;
+
+/// A message shown to the player for [`NOTICE_LIFETIME`] seconds.
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub message: String,
+    /// Time when the notice was received ([`Time::elapsed_seconds`](bevy::time::Time::elapsed_seconds)).
+    pub time: f32,
 }
 
+/// Messages broadcast by the server, see [`ServerMessages::Broadcast`].
+#[derive(Debug, Default, Resource)]
+pub struct Notices(pub Vec<Notice>);
+
+impl Notices {
+    pub fn push(&mut self, message: String, time: f32) {
+        log::info!("Notice: {}", message);
+        self.0.retain(|notice| time - notice.time < NOTICE_LIFETIME);
+        self.0.push(Notice { message, time });
+    }
+
+    /// Returns the notices that must be shown at the time.
+    pub fn actual(&self, time: f32) -> impl Iterator<Item = &Notice> {
+        self.0
+            .iter()
+            .filter(move |notice| time - notice.time < NOTICE_LIFETIME)
+    }
+}
+
+/// Usernames of the players that are still loading the current map.
+#[derive(Debug, Default, Resource, PartialEq)]
+pub struct LoadingPlayers(pub Vec<String>);
+
 #[derive(Debug, Default, Resource)]
 pub struct Lobby {
+    /// Name of the lobby from [`ServerSettings`](crate::settings::ServerSettings).
+    pub name: String,
     pub players: HashMap<PlayerId, PlayerData>,
     pub players_seq: usize,
 }
 
+impl Lobby {
+    /// Returns the username of the player, or `"unknown"` if the player has left.
+    pub fn username(&self, player_id: PlayerId) -> &str {
+        self.players
+            .get(&player_id)
+            .map_or("unknown", |player_data| player_data.username.as_str())
+    }
+
+    /// Returns the number of players in the team.
+    pub fn team_size(&self, team: Team) -> usize {
+        self.players
+            .values()
+            .filter(|player_data| player_data.team == Some(team))
+            .count()
+    }
+
+    /// Returns the team with the fewest players, new players join it.
+    pub fn smallest_team(&self) -> Team {
+        Team::ALL
+            .into_iter()
+            .min_by_key(|team| self.team_size(*team))
+            .unwrap()
+    }
+
+    /// Returns `true` if both players are in the same team.
+    pub fn are_teammates(&self, player: PlayerId, other: PlayerId) -> bool {
+        let team = |player_id| {
+            self.players
+                .get(&player_id)
+                .and_then(|player_data| player_data.team)
+        };
+        team(player).is_some_and(|team_of_player| Some(team_of_player) == team(other))
+    }
+
+    /// Returns the notice about the death of a character.
+    pub fn death_message(&self, victim: PlayerId, killer: Option<PlayerId>) -> String {
+        match killer {
+            Some(killer) if killer == victim => {
+                format!("{} killed themselves", self.username(victim))
+            }
+            Some(killer) => format!("{} killed {}", self.username(killer), self.username(victim)),
+            None => format!("{} died", self.username(victim)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum PlayerId {
     HostOrSingle,
@@ -173,6 +523,15 @@ pub struct PlayerData {
     pub entity: Entity,
     pub color: Color,
     pub username: String,
+    /// Team of the player in team modes.
+    pub team: Option<Team>,
+}
+
+impl PlayerData {
+    /// Returns the color of the character, tinted by the team of the player.
+    pub fn character_color(&self) -> Color {
+        self.team.map_or(self.color, |team| team.tint(self.color))
+    }
 }
 
 // TODO resource????????
@@ -206,12 +565,20 @@ impl PlayerInputs {
         self.input.turn_vertical += input.turn_vertical;
         self.input.special |= input.special;
         self.input.fire |= input.fire;
+        self.input.weapon = input.weapon.or(self.input.weapon);
+        self.input.reload |= input.reload;
+        self.input.shot = input.shot;
     }
 
     pub fn get(&self) -> Inputs {
         self.input
     }
 
+    /// Sets the number of the latest predicted shot, see [`Inputs::shot`].
+    pub fn set_shot(&mut self, shot: u32) {
+        self.input.shot = shot;
+    }
+
     pub fn is_input_changed(&self, input_type: InputType) -> bool {
         match input_type {
             InputType::Up => self.input.up != self.previouse_input.up,
@@ -353,6 +720,12 @@ pub struct Inputs {
     pub turn_vertical: f32,
     pub special: bool,
     pub fire: bool,
+    /// Index of the weapon the player selects, see [`Weapons`].
+    pub weapon: Option<u8>,
+    pub reload: bool,
+    /// Number of the latest shot the client has predicted, the server echoes it
+    /// in the messages about shots fired with these inputs.
+    pub shot: u32,
 }
 
 #[derive(Debug, Component)]
@@ -360,14 +733,14 @@ pub struct Character {
     pub id: PlayerId,
 }
 
-#[derive(Resource, Default, Debug, Serialize, Deserialize)]
+#[derive(Resource, Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct PlayerTransportData {
     pub position: Vec3,
     pub rotation: Quat,
     pub player_view: PlayerView,
 }
 
-#[derive(Resource, Default, Debug, Serialize, Deserialize)]
+#[derive(Resource, Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct ActorTransportData {
     pub position: Vec3,
     pub rotation: Quat,
@@ -379,6 +752,51 @@ pub struct TransportData {
     pub actors: HashMap<LinkId, ActorTransportData>,
 }
 
+/// A player in a [`WorldState`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub id: PlayerId,
+    pub color: Color,
+    pub username: String,
+    pub team: Option<Team>,
+    pub health: Health,
+    pub weapon: WeaponState,
+    /// Set while the character waits for its respawn.
+    pub dead: Option<Dead>,
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+/// Type and appearance of an actor in a [`WorldState`].
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ActorKind {
+    Projectile {
+        color: Color,
+    },
+    /// A prop of the map scene, it exists on the client already.
+    Scene,
+}
+
+/// An actor in a [`WorldState`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActorState {
+    pub id: LinkId,
+    pub kind: ActorKind,
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+/// Everything a client must know to play on the current map.
+///
+/// Replaces the players and projectiles of the client, so late joiners see
+/// projectiles in flight and moved props before the next snapshot includes them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorldState {
+    pub map_state: MapState,
+    pub players: Vec<PlayerState>,
+    pub actors: Vec<ActorState>,
+}
+
 #[derive(Resource, Default, Debug, Serialize, Deserialize)]
 pub struct TransportDataResource {
     pub data: TransportData,
@@ -411,6 +829,8 @@ impl Plugin for LobbyPlugins {
             .add_state::<MapLoaderState>()
             .init_resource::<HostResource>()
             .init_resource::<ClientResource>()
+            .init_resource::<LoadingPlayers>()
+            .init_resource::<Notices>()
             .add_plugins((SingleLobbyPlugins, HostLobbyPlugins, ClientLobbyPlugins));
     }
 }
@@ -0,0 +1,76 @@
+use bevy::ecs::event::EventWriter;
+use bevy::ecs::system::Resource;
+
+use crate::map::{open_custom_map, CustomMap, MapState};
+use crate::settings::RotationMap;
+
+use super::ChangeMapLobbyEvent;
+
+/// Plays the maps of [`ServerSettings::map_rotation`](crate::settings::ServerSettings::map_rotation)
+/// one after another, the next map is loaded when a round is over.
+///
+/// The time limit of the current map is the time of the round, see
+/// [`RoundTimer`](crate::mode::RoundTimer).
+#[derive(Debug, Resource)]
+pub struct MapRotation {
+    maps: Vec<RotationMap>,
+    index: usize,
+}
+
+impl MapRotation {
+    pub fn new(maps: Vec<RotationMap>) -> Self {
+        Self {
+            // the first `advance` starts the rotation from the first map
+            index: maps.len().saturating_sub(1),
+            maps,
+        }
+    }
+
+    /// Returns the map of the rotation that is played now.
+    pub fn current(&self) -> Option<&RotationMap> {
+        self.maps.get(self.index)
+    }
+
+    /// Returns the time limit in seconds of the current map.
+    pub fn time_limit(&self) -> Option<f32> {
+        self.current()
+            .and_then(|rotation_map| rotation_map.time_limit)
+    }
+
+    /// Returns `true` if the rotation has other maps than the current one.
+    pub fn has_next(&self) -> bool {
+        self.maps.len() > 1
+    }
+
+    /// Changes the lobby to the next map of the rotation, maps that can not be loaded are skipped.
+    ///
+    /// Returns `false` if no map of the rotation can be loaded.
+    pub fn advance(
+        &mut self,
+        custom_map: &mut CustomMap,
+        change_map_event: &mut EventWriter<ChangeMapLobbyEvent>,
+    ) -> bool {
+        for _ in 0..self.maps.len() {
+            self.index = (self.index + 1) % self.maps.len();
+            let rotation_map = &self.maps[self.index];
+
+            if rotation_map.map == MapState::Custom {
+                let Some(name) = rotation_map.file.as_ref() else {
+                    log::error!("Custom map of the rotation has no file");
+                    continue;
+                };
+                match open_custom_map(name) {
+                    Ok(local) => custom_map.0 = Some(local),
+                    Err(err) => {
+                        log::error!("Failed to open custom map {}: {}", name, err);
+                        continue;
+                    }
+                }
+            }
+
+            change_map_event.send(ChangeMapLobbyEvent(rotation_map.map));
+            return true;
+        }
+        false
+    }
+}
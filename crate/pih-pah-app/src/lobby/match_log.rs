@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::ecs::event::{Event, EventReader};
+use bevy::ecs::system::{Res, ResMut, Resource};
+use serde::{Deserialize, Serialize};
+
+use crate::character::DeathEvent;
+use crate::component::RespawnEvent;
+use crate::map::MapState;
+use crate::world::LinkId;
+
+use crate::mode::RoundRestartEvent;
+
+use super::host::SpawnProjectileEvent;
+use super::{ChangeMapLobbyEvent, Lobby};
+
+/// Folder next to the executable with one JSON Lines file per match.
+pub const MATCH_HISTORY_FOLDER: &str = "match_history";
+
+/// Seconds between flushes of the buffered events, a match is flushed whole when it ends.
+pub const MATCH_LOG_FLUSH_INTERVAL: f64 = 5.;
+
+/// A significant event of a match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MatchEvent {
+    /// The lobby has been created or a new round has begun, the players already in the
+    /// lobby follow as [`MatchEvent::PlayerConnected`].
+    MatchStart {
+        lobby: String,
+        host: String,
+    },
+    PlayerConnected {
+        client_id: u64,
+        username: String,
+    },
+    PlayerDisconnected {
+        client_id: u64,
+        username: String,
+        reason: String,
+    },
+    MapChange {
+        map: MapState,
+    },
+    ProjectileSpawn {
+        id: usize,
+    },
+    Kill {
+        killer: Option<String>,
+        victim: String,
+    },
+    /// A character has been respawned.
+    Respawn {
+        username: String,
+        reason: String,
+    },
+    /// A round has been won, `winner` is `None` on a draw.
+    RoundEnd {
+        winner: Option<String>,
+    },
+    /// The round is over and the next one is written to a new file, or the lobby has been closed.
+    MatchEnd,
+}
+
+/// One line of a match history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    /// Seconds since the Unix epoch.
+    pub time: f64,
+    #[serde(flatten)]
+    pub event: MatchEvent,
+}
+
+/// Sent by lobby systems to add an event to the [`MatchLog`].
+#[derive(Debug, Event)]
+pub struct MatchLogEvent(pub MatchEvent);
+
+/// The history file of the current match.
+///
+/// A match is one round on one map, the log moves on to a new file whenever the map changes
+/// or the round restarts.
+#[derive(Debug, Resource)]
+pub struct MatchLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    lobby: String,
+    host: String,
+    map: Option<MapState>,
+    flushed_at: f64,
+}
+
+impl MatchLog {
+    /// Creates a new history file in [`MATCH_HISTORY_FOLDER`] starting with
+    /// [`MatchEvent::MatchStart`].
+    pub fn create(lobby: String, host: String) -> io::Result<Self> {
+        let exe_path = std::env::current_exe()?;
+        let folder = exe_path
+            .parent()
+            .map(|dir| dir.join(MATCH_HISTORY_FOLDER))
+            .unwrap_or_else(|| PathBuf::from(MATCH_HISTORY_FOLDER));
+        fs::create_dir_all(&folder)?;
+
+        // milliseconds, a round can be restarted within the second the previous one started
+        let path = folder.join(format!("{}.jsonl", (now() * 1000.) as u64));
+        let writer = BufWriter::new(File::create(&path)?);
+
+        let mut match_log = Self {
+            path,
+            writer,
+            lobby,
+            host,
+            map: None,
+            flushed_at: now(),
+        };
+        match_log.write(MatchEvent::MatchStart {
+            lobby: match_log.lobby.clone(),
+            host: match_log.host.clone(),
+        });
+
+        Ok(match_log)
+    }
+
+    /// Ends the current match and continues in a new file on `map`.
+    ///
+    /// The first map of the lobby is written to the file made by [`MatchLog::create`].
+    fn start_match(&mut self, map: MapState, lobby: &Lobby) {
+        if self.map.is_some() {
+            self.write(MatchEvent::MatchEnd);
+            self.flush();
+
+            match Self::create(self.lobby.clone(), self.host.clone()) {
+                Ok(match_log) => {
+                    *self = match_log;
+                    log::info!("Match history is written to {:?}", self.path);
+                }
+                Err(err) => log::error!("Failed to create match history: {}", err),
+            }
+
+            for (player_id, player_data) in lobby.players.iter() {
+                if let Some(client_id) = player_id.client_id() {
+                    self.write(MatchEvent::PlayerConnected {
+                        client_id: client_id.raw(),
+                        username: player_data.username.clone(),
+                    });
+                }
+            }
+        }
+
+        self.map = Some(map);
+        self.write(MatchEvent::MapChange { map });
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the event with the current time.
+    pub fn write(&mut self, event: MatchEvent) {
+        let record = MatchRecord { time: now(), event };
+        let result = serde_json::to_writer(&mut self.writer, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(err) = result {
+            log::error!("Failed to write match history {:?}: {}", self.path, err);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.flushed_at = now();
+        if let Err(err) = self.writer.flush() {
+            log::error!("Failed to write match history {:?}: {}", self.path, err);
+        }
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Writes the events of the lobby into the [`MatchLog`].
+#[allow(clippy::too_many_arguments)]
+pub fn write_match_log(
+    mut match_log: ResMut<MatchLog>,
+    mut match_log_event: EventReader<MatchLogEvent>,
+    mut change_map_event: EventReader<ChangeMapLobbyEvent>,
+    mut round_restart_event: EventReader<RoundRestartEvent>,
+    mut spawn_projectile_event: EventReader<SpawnProjectileEvent>,
+    mut respawn_event: EventReader<RespawnEvent>,
+    mut death_event: EventReader<DeathEvent>,
+    lobby: Res<Lobby>,
+) {
+    // only the last map is played when the rotation skips maps within a frame
+    let next_map = change_map_event
+        .read()
+        .last()
+        .map(|ChangeMapLobbyEvent(map)| *map)
+        .or_else(|| {
+            round_restart_event
+                .read()
+                .last()
+                .and_then(|_| match_log.map)
+        });
+    round_restart_event.clear();
+    if let Some(map) = next_map {
+        match_log.start_match(map, &lobby);
+    }
+
+    let mut round_ended = false;
+    for MatchLogEvent(event) in match_log_event.read() {
+        round_ended |= matches!(event, MatchEvent::RoundEnd { .. });
+        match_log.write(event.clone());
+    }
+    for SpawnProjectileEvent(link_id, ..) in spawn_projectile_event.read() {
+        if let LinkId::Projectile(id) = link_id {
+            match_log.write(MatchEvent::ProjectileSpawn { id: *id });
+        }
+    }
+    for DeathEvent { victim, killer, .. } in death_event.read() {
+        match_log.write(MatchEvent::Kill {
+            killer: killer.map(|killer| lobby.username(killer).to_string()),
+            victim: lobby.username(*victim).to_string(),
+        });
+    }
+    for RespawnEvent { entity, reason } in respawn_event.read() {
+        let player = lobby
+            .players
+            .values()
+            .find(|player_data| player_data.entity == *entity);
+        if let Some(player_data) = player {
+            match_log.write(MatchEvent::Respawn {
+                username: player_data.username.clone(),
+                reason: reason.name().to_string(),
+            });
+        }
+    }
+
+    if round_ended || now() - match_log.flushed_at >= MATCH_LOG_FLUSH_INTERVAL {
+        match_log.flush();
+    }
+}
+
+/// Statistics of one player in a [`MatchSummary`].
+#[derive(Debug, Default)]
+pub struct PlayerSummary {
+    pub connects: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub respawns: u32,
+}
+
+/// Summary of a match history file.
+#[derive(Debug, Default)]
+pub struct MatchSummary {
+    pub lobby: Option<String>,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub maps: Vec<MapState>,
+    pub projectiles: u32,
+    pub respawns: BTreeMap<String, u32>,
+    pub players: BTreeMap<String, PlayerSummary>,
+}
+
+impl MatchSummary {
+    /// Reads a match history file, lines that can not be parsed are skipped.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut summary = Self::default();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let Ok(record) = serde_json::from_str::<MatchRecord>(&line) else {
+                log::warn!("Skipped match history line: {}", line);
+                continue;
+            };
+
+            summary.start.get_or_insert(record.time);
+            summary.end = Some(record.time);
+            match record.event {
+                MatchEvent::MatchStart { lobby, host } => {
+                    summary.lobby = Some(lobby);
+                    summary.players.entry(host).or_default().connects += 1;
+                }
+                MatchEvent::PlayerConnected { username, .. } => {
+                    summary.players.entry(username).or_default().connects += 1;
+                }
+                MatchEvent::PlayerDisconnected { .. }
+                | MatchEvent::RoundEnd { .. }
+                | MatchEvent::MatchEnd => {}
+                MatchEvent::MapChange { map } => summary.maps.push(map),
+                MatchEvent::ProjectileSpawn { .. } => summary.projectiles += 1,
+                MatchEvent::Kill { killer, victim } => {
+                    if let Some(killer) = killer {
+                        summary.players.entry(killer).or_default().kills += 1;
+                    }
+                    summary.players.entry(victim).or_default().deaths += 1;
+                }
+                MatchEvent::Respawn { username, reason } => {
+                    summary.players.entry(username).or_default().respawns += 1;
+                    *summary.respawns.entry(reason).or_default() += 1;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+impl Display for MatchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Lobby: {}", self.lobby.as_deref().unwrap_or("unknown"))?;
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            writeln!(f, "Duration: {:.0}s", end - start)?;
+        }
+        let maps: Vec<String> = self.maps.iter().map(|map| map.to_string()).collect();
+        writeln!(f, "Maps: {}", maps.join(", "))?;
+        writeln!(f, "Projectiles: {}", self.projectiles)?;
+        for (reason, count) in &self.respawns {
+            writeln!(f, "Respawns ({}): {}", reason, count)?;
+        }
+        writeln!(f, "Players:")?;
+        for (username, player) in &self.players {
+            writeln!(
+                f,
+                "  {}: {} kills, {} deaths, {} respawns, {} connects",
+                username, player.kills, player.deaths, player.respawns, player.connects
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use bevy::ecs::event::EventReader;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Query, Res, ResMut, Resource};
+use bevy::time::Time;
+use renet::RenetServer;
+
+use crate::actor::Tracepoint;
+use crate::component::RespawnEvent;
+use crate::world::LinkId;
+
+use super::{ChangeMapLobbyEvent, Character, Lobby};
+
+/// Longest request a metrics scraper can send.
+pub const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// A scraper connection waiting for the end of its request.
+#[derive(Debug)]
+struct MetricsRequest {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    closed: bool,
+}
+
+impl MetricsRequest {
+    /// Reads the request, returns `true` when all of its headers have been received.
+    fn read(&mut self) -> bool {
+        let mut data = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut data) {
+                Ok(0) => {
+                    self.closed = true;
+                    return false;
+                }
+                Ok(len) => self.buffer.extend_from_slice(&data[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    return false;
+                }
+            }
+        }
+
+        if self.buffer.len() > MAX_REQUEST_LENGTH {
+            self.closed = true;
+            return false;
+        }
+        self.buffer.windows(4).any(|window| window == b"\r\n\r\n")
+    }
+}
+
+/// Counters and gauges of the hosted lobby, served in the Prometheus text format.
+///
+/// Scrapers read them with `GET` from [`ServerSettings::metrics_port`](crate::settings::ServerSettings::metrics_port)
+/// on the local machine, every path returns all metrics.
+#[derive(Debug, Resource)]
+pub struct Metrics {
+    listener: TcpListener,
+    requests: Vec<MetricsRequest>,
+    snapshot_bytes_total: u64,
+    /// Snapshot bytes sent since `window_start`.
+    snapshot_bytes_window: u64,
+    window_start: f32,
+    snapshot_bytes_per_second: f64,
+    frame_interval: f32,
+    respawns_total: BTreeMap<&'static str, u64>,
+    map_changes_total: u64,
+}
+
+impl Metrics {
+    /// Listens on the port of the local machine.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            requests: Vec::new(),
+            snapshot_bytes_total: 0,
+            snapshot_bytes_window: 0,
+            window_start: 0.,
+            snapshot_bytes_per_second: 0.,
+            frame_interval: 0.,
+            respawns_total: BTreeMap::new(),
+            map_changes_total: 0,
+        })
+    }
+
+    /// Counts bytes of snapshots sent to clients.
+    pub fn record_snapshot(&mut self, bytes: usize) {
+        self.snapshot_bytes_total += bytes as u64;
+        self.snapshot_bytes_window += bytes as u64;
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.requests.push(MetricsRequest {
+                            stream,
+                            buffer: Vec::new(),
+                            closed: false,
+                        });
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    log::error!("Metrics failed to accept a connection: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Writes one metric with its help and type lines.
+fn write_metric(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(Option<(&str, &str)>, f64)],
+) {
+    let _ = writeln!(text, "# HELP pih_pah_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE pih_pah_{} {}", name, kind);
+    for (label, value) in samples {
+        match label {
+            Some((key, label_value)) => {
+                let _ = writeln!(
+                    text,
+                    "pih_pah_{}{{{}=\"{}\"}} {}",
+                    name, key, label_value, value
+                );
+            }
+            None => {
+                let _ = writeln!(text, "pih_pah_{} {}", name, value);
+            }
+        }
+    }
+}
+
+/// Updates the metrics that are counted from events and frame times.
+pub fn collect_metrics(
+    mut metrics: ResMut<Metrics>,
+    mut respawn_event: EventReader<RespawnEvent>,
+    mut change_map_event: EventReader<ChangeMapLobbyEvent>,
+    time: Res<Time>,
+) {
+    metrics.frame_interval = time.delta_seconds();
+
+    let now = time.elapsed_seconds();
+    let window = now - metrics.window_start;
+    if window >= 1. {
+        metrics.snapshot_bytes_per_second = metrics.snapshot_bytes_window as f64 / window as f64;
+        metrics.snapshot_bytes_window = 0;
+        metrics.window_start = now;
+    }
+
+    for RespawnEvent { reason, .. } in respawn_event.read() {
+        *metrics.respawns_total.entry(reason.name()).or_default() += 1;
+    }
+    metrics.map_changes_total += change_map_event.read().count() as u64;
+}
+
+/// Answers the scrapers with the current metrics.
+pub fn serve_metrics(
+    mut metrics: ResMut<Metrics>,
+    server: Res<RenetServer>,
+    lobby: Res<Lobby>,
+    character_query: Query<(), With<Character>>,
+    link_id_query: Query<&LinkId>,
+    tracepoint_query: Query<(), With<Tracepoint>>,
+) {
+    metrics.accept();
+    if metrics.requests.is_empty() {
+        return;
+    }
+
+    let projectiles = link_id_query
+        .iter()
+        .filter(|link_id| matches!(link_id, LinkId::Projectile(_)))
+        .count();
+
+    let mut text = String::new();
+    write_metric(
+        &mut text,
+        "players",
+        "gauge",
+        "Players in the lobby, including the host.",
+        &[(None, lobby.players.len() as f64)],
+    );
+    write_metric(
+        &mut text,
+        "connected_clients",
+        "gauge",
+        "Connected clients, including the loading ones.",
+        &[(None, server.connected_clients() as f64)],
+    );
+    write_metric(
+        &mut text,
+        "snapshot_bytes_total",
+        "counter",
+        "Bytes of snapshots sent to clients.",
+        &[(None, metrics.snapshot_bytes_total as f64)],
+    );
+    write_metric(
+        &mut text,
+        "snapshot_bytes_per_second",
+        "gauge",
+        "Bytes of snapshots sent to clients during the last second.",
+        &[(None, metrics.snapshot_bytes_per_second)],
+    );
+    write_metric(
+        &mut text,
+        "frame_interval_seconds",
+        "gauge",
+        "Time between the last two frames, including the wait for the next frame.",
+        &[(None, metrics.frame_interval as f64)],
+    );
+    write_metric(
+        &mut text,
+        "entities",
+        "gauge",
+        "Entities on the map by kind.",
+        &[
+            (
+                Some(("kind", "character")),
+                character_query.iter().count() as f64,
+            ),
+            (Some(("kind", "projectile")), projectiles as f64),
+            (
+                Some(("kind", "tracepoint")),
+                tracepoint_query.iter().count() as f64,
+            ),
+        ],
+    );
+    let respawns: Vec<_> = metrics
+        .respawns_total
+        .iter()
+        .map(|(reason, count)| (Some(("reason", *reason)), *count as f64))
+        .collect();
+    write_metric(
+        &mut text,
+        "respawns_total",
+        "counter",
+        "Respawns by the reason that caused them.",
+        &respawns,
+    );
+    write_metric(
+        &mut text,
+        "map_changes_total",
+        "counter",
+        "Map changes since the lobby has been created.",
+        &[(None, metrics.map_changes_total as f64)],
+    );
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        text.len(),
+        text
+    );
+    for request in metrics.requests.iter_mut() {
+        if request.read() {
+            let _ = request.stream.write_all(response.as_bytes());
+            request.closed = true;
+        }
+    }
+    metrics.requests.retain(|request| !request.closed);
+}
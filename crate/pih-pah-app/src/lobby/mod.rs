@@ -2,8 +2,16 @@
 
 mod lobby;
 
+pub mod anti_cheat;
 pub mod client;
+pub mod clock;
+pub mod compression;
 pub mod host;
+pub mod map_rotation;
+pub mod match_log;
+pub mod metrics;
+pub mod priority;
+pub mod rcon;
 pub mod single;
 
 pub use lobby::*;
@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::ecs::system::Resource;
+use bevy::math::Vec3;
+use renet::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::world::LinkId;
+
+use super::{ActorTransportData, PlayerId, PlayerTransportData, TransportData};
+
+/// Largest snapshot, it fits into one packet below the MTU together with the netcode and renet headers.
+pub const MAX_SNAPSHOT_BYTES: usize = 1100;
+/// Bytes of the two map lengths at the start of a serialized [`TransportData`].
+const SNAPSHOT_HEADER_BYTES: usize = 16;
+/// Distance from the client's character at which the priority grows half as fast.
+const PRIORITY_DISTANCE: f32 = 50.;
+/// Speed at which the priority grows twice as fast.
+const PRIORITY_SPEED: f32 = 20.;
+
+/// Replicated state of one character or actor.
+#[derive(Debug, Clone)]
+pub enum SnapshotItem {
+    Player(PlayerId, PlayerTransportData),
+    Actor(LinkId, ActorTransportData),
+}
+
+/// Identifies a [`SnapshotItem`] between snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SnapshotKey {
+    Player(PlayerId),
+    Actor(LinkId),
+}
+
+impl SnapshotItem {
+    pub fn key(&self) -> SnapshotKey {
+        match self {
+            SnapshotItem::Player(player_id, _) => SnapshotKey::Player(*player_id),
+            SnapshotItem::Actor(link_id, _) => SnapshotKey::Actor(link_id.clone()),
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        match self {
+            SnapshotItem::Player(_, data) => data.position,
+            SnapshotItem::Actor(_, data) => data.position,
+        }
+    }
+
+    /// Bytes the item takes in a serialized [`TransportData`].
+    fn size(&self) -> usize {
+        let size = match self {
+            SnapshotItem::Player(player_id, data) => bincode::serialized_size(&(player_id, data)),
+            SnapshotItem::Actor(link_id, data) => bincode::serialized_size(&(link_id, data)),
+        };
+        size.unwrap_or(u64::MAX) as usize
+    }
+}
+
+/// Priorities of the replicated items accumulated for every client.
+///
+/// The priority of an item grows every tick it is not sent, faster when it is close
+/// to the client's character or moves fast, and drops to zero when it is sent.
+/// Items that do not fit into the budget of a tick are sent later, so nothing starves.
+#[derive(Debug, Default, Resource)]
+pub struct SnapshotPriorities(HashMap<ClientId, HashMap<SnapshotKey, f32>>);
+
+impl SnapshotPriorities {
+    /// Builds the snapshot of the client from the relevant items and their velocities,
+    /// filling `budget` bytes from the highest priority down.
+    ///
+    /// The snapshot never exceeds [`MAX_SNAPSHOT_BYTES`],
+    /// the client's own character is sent every time.
+    /// Also returns the items that have left the relevance radius since the previous snapshot,
+    /// the client must be told to hide them, a missing update may just be a delayed one.
+    pub fn snapshot<'a>(
+        &mut self,
+        client_id: ClientId,
+        viewer: Option<Vec3>,
+        items: impl Iterator<Item = &'a (SnapshotItem, Vec3)>,
+        delta: f32,
+        budget: usize,
+    ) -> (TransportData, Vec<SnapshotKey>) {
+        let own = SnapshotKey::Player(PlayerId::Client(client_id));
+        let priorities = self.0.entry(client_id).or_default();
+
+        let mut candidates = Vec::new();
+        for (item, velocity) in items {
+            let distance = viewer.map_or(0., |viewer| viewer.distance(item.position()));
+            let rate =
+                (1. + velocity.length() / PRIORITY_SPEED) / (1. + distance / PRIORITY_DISTANCE);
+
+            let key = item.key();
+            let priority = priorities.entry(key.clone()).or_default();
+            *priority += rate * delta;
+            let priority = if key == own { f32::INFINITY } else { *priority };
+            candidates.push((priority, key, item));
+        }
+
+        // forget items that have left the relevance radius or do not exist anymore
+        let relevant: HashSet<&SnapshotKey> = candidates.iter().map(|(_, key, _)| key).collect();
+        let mut left = Vec::new();
+        priorities.retain(|key, _| {
+            let is_relevant = relevant.contains(key);
+            if !is_relevant {
+                left.push(key.clone());
+            }
+            is_relevant
+        });
+
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let budget = budget.min(MAX_SNAPSHOT_BYTES);
+        let mut size = SNAPSHOT_HEADER_BYTES;
+        let mut data = TransportData::default();
+        for (_, key, item) in candidates {
+            // a smaller item further down may still fit
+            let item_size = item.size();
+            if size + item_size > budget {
+                continue;
+            }
+            size += item_size;
+            priorities.insert(key, 0.);
+
+            match item {
+                SnapshotItem::Player(player_id, player_data) => {
+                    data.players.insert(*player_id, *player_data);
+                }
+                SnapshotItem::Actor(link_id, actor_data) => {
+                    data.actors.insert(link_id.clone(), *actor_data);
+                }
+            }
+        }
+
+        (data, left)
+    }
+
+    /// Forgets the priorities of the clients that are not ready anymore.
+    pub fn retain_clients(&mut self, clients: &[ClientId]) {
+        self.0.retain(|client_id, _| clients.contains(client_id));
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use bevy::ecs::event::EventWriter;
+use bevy::ecs::schedule::State;
+use bevy::ecs::system::{Res, ResMut, Resource};
+use bevy::time::Time;
+use renet::{DefaultChannel, RenetServer};
+
+use crate::map::{open_custom_map, CustomMap, MapState};
+use crate::mode::RoundTimer;
+use crate::settings::ServerSettings;
+
+use super::host::ClientsLoadState;
+use super::{ChangeMapLobbyEvent, Lobby, Notices, PlayerId, ServerMessages};
+
+/// Longest command line an operator can send.
+pub const MAX_COMMAND_LENGTH: usize = 4096;
+/// Wrong passwords after which the connection is closed.
+pub const MAX_AUTH_ATTEMPTS: u32 = 3;
+
+const HELP: &str = "auth <password> - log in
+players - list connected players
+kick <id|username> - disconnect a player
+map <ShootingRange|GravityHell> - change the map
+map Custom <file> - change to a custom map from the maps folder
+say <message> - broadcast a message to all players
+stats - show lobby statistics
+quit - close the connection";
+
+/// An operator connected to the remote admin console.
+#[derive(Debug)]
+struct RconConnection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    authenticated: bool,
+    auth_attempts: u32,
+    closed: bool,
+}
+
+impl RconConnection {
+    /// Reads everything the operator has sent and returns the complete command lines.
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut data = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut data) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(len) => self.buffer.extend_from_slice(&data[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        if self.buffer.len() > MAX_COMMAND_LENGTH {
+            self.closed = true;
+        }
+        lines
+    }
+
+    /// Sends the response lines, the response ends with an empty line.
+    fn respond(&mut self, response: &str) {
+        let mut text = String::new();
+        for line in response.lines().filter(|line| !line.is_empty()) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push('\n');
+        if self.stream.write_all(text.as_bytes()).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+/// Remote admin console of the hosted lobby.
+///
+/// Operators connect to [`ServerSettings::rcon_port`] on the local machine,
+/// log in with [`ServerSettings::rcon_password`] and send text commands line by line.
+#[derive(Debug, Resource)]
+pub struct Rcon {
+    listener: TcpListener,
+    password: String,
+    connections: Vec<RconConnection>,
+}
+
+impl Rcon {
+    /// Listens on the port of the local machine.
+    pub fn bind(port: u16, password: String) -> io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            password,
+            connections: Vec::new(),
+        })
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if stream.set_nonblocking(true).is_err() {
+                        continue;
+                    }
+                    log::info!("Rcon connection from {}", addr);
+                    self.connections.push(RconConnection {
+                        stream,
+                        buffer: Vec::new(),
+                        authenticated: false,
+                        auth_attempts: 0,
+                        closed: false,
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    log::error!("Rcon failed to accept a connection: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Accepts operators and executes their commands.
+#[allow(clippy::too_many_arguments)]
+pub fn rcon_update(
+    mut rcon: ResMut<Rcon>,
+    mut server: ResMut<RenetServer>,
+    lobby: Res<Lobby>,
+    clients_load_state: Res<ClientsLoadState>,
+    round_timer: Res<RoundTimer>,
+    server_settings: Res<ServerSettings>,
+    map_state: Res<State<MapState>>,
+    mut custom_map: ResMut<CustomMap>,
+    mut change_map_event: EventWriter<ChangeMapLobbyEvent>,
+    mut notices: ResMut<Notices>,
+    time: Res<Time>,
+) {
+    rcon.accept();
+
+    let rcon = rcon.as_mut();
+    for connection in rcon.connections.iter_mut() {
+        for line in connection.read_lines() {
+            if line.is_empty() {
+                continue;
+            }
+            let (command, argument) = match line.split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (line.as_str(), ""),
+            };
+
+            if command == "auth" {
+                if argument == rcon.password {
+                    connection.authenticated = true;
+                    connection.respond("ok");
+                } else {
+                    connection.auth_attempts += 1;
+                    log::warn!("Rcon wrong password");
+                    connection.respond("wrong password");
+                    if connection.auth_attempts >= MAX_AUTH_ATTEMPTS {
+                        connection.closed = true;
+                    }
+                }
+                continue;
+            }
+            if !connection.authenticated {
+                connection.respond("not authenticated, use auth <password>");
+                continue;
+            }
+
+            log::info!("Rcon command: {}", line);
+            let response = match command {
+                "help" => HELP.to_string(),
+                "players" => players(&server, &lobby, &clients_load_state),
+                "kick" => kick(&mut server, &clients_load_state, argument),
+                "map" => change_map(argument, &mut custom_map, &mut change_map_event),
+                "say" if !argument.is_empty() => {
+                    let message = bincode::serialize(&ServerMessages::Broadcast {
+                        message: argument.to_string(),
+                    })
+                    .unwrap();
+                    server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+                    notices.push(argument.to_string(), time.elapsed_seconds());
+                    "ok".to_string()
+                }
+                "stats" => stats(
+                    &server,
+                    &lobby,
+                    &clients_load_state,
+                    &round_timer,
+                    &server_settings,
+                    *map_state.get(),
+                    time.elapsed_seconds(),
+                ),
+                "quit" => {
+                    connection.closed = true;
+                    continue;
+                }
+                _ => format!("unknown command {}, use help", command),
+            };
+            connection.respond(&response);
+        }
+    }
+
+    rcon.connections.retain(|connection| !connection.closed);
+}
+
+fn players(server: &RenetServer, lobby: &Lobby, clients_load_state: &ClientsLoadState) -> String {
+    let mut lines = Vec::new();
+    if let Some(host) = lobby.players.get(&PlayerId::HostOrSingle) {
+        lines.push(format!("host {} playing", host.username));
+    }
+
+    let mut clients: Vec<_> = clients_load_state.clients.iter().collect();
+    clients.sort_by_key(|(client_id, _)| client_id.raw());
+    for (client_id, client) in clients {
+        let rtt = server
+            .network_info(*client_id)
+            .map(|info| format!("{:.0}ms", info.rtt))
+            .unwrap_or_default();
+        lines.push(format!(
+            "{} {} {:?} {}",
+            client_id, client.username, client.state, rtt
+        ));
+    }
+
+    if lines.is_empty() {
+        "no players".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+fn kick(server: &mut RenetServer, clients_load_state: &ClientsLoadState, target: &str) -> String {
+    let client_id = clients_load_state
+        .clients
+        .iter()
+        .find(|(client_id, client)| client_id.to_string() == target || client.username == target)
+        .map(|(client_id, _)| *client_id);
+
+    match client_id {
+        Some(client_id) => {
+            log::info!("Rcon kicks client {}", client_id);
+            server.disconnect(client_id);
+            format!("kicked {}", client_id)
+        }
+        None => format!("player {} not found", target),
+    }
+}
+
+fn change_map(
+    argument: &str,
+    custom_map: &mut CustomMap,
+    change_map_event: &mut EventWriter<ChangeMapLobbyEvent>,
+) -> String {
+    let (name, file) = match argument.split_once(' ') {
+        Some((name, file)) => (name, Some(file.trim())),
+        None => (argument, None),
+    };
+    let map_state: MapState = match name.parse() {
+        Ok(MapState::Menu) => return "the lobby can not change to the menu".to_string(),
+        Ok(map_state) => map_state,
+        Err(err) => return err,
+    };
+
+    if map_state == MapState::Custom {
+        let Some(file) = file else {
+            return "usage: map Custom <file>".to_string();
+        };
+        match open_custom_map(file) {
+            Ok(local) => custom_map.0 = Some(local),
+            Err(err) => return format!("failed to open {}: {}", file, err),
+        }
+    }
+
+    change_map_event.send(ChangeMapLobbyEvent(map_state));
+    format!("changing map to {}", map_state)
+}
+
+fn stats(
+    server: &RenetServer,
+    lobby: &Lobby,
+    clients_load_state: &ClientsLoadState,
+    round_timer: &RoundTimer,
+    server_settings: &ServerSettings,
+    map_state: MapState,
+    uptime: f32,
+) -> String {
+    let clients = server.clients_id();
+    let (sent, received) = clients
+        .iter()
+        .filter_map(|client_id| server.network_info(*client_id).ok())
+        .fold((0., 0.), |(sent, received), info| {
+            (
+                sent + info.bytes_sent_per_second,
+                received + info.bytes_received_per_second,
+            )
+        });
+
+    let mut lines = vec![
+        format!("lobby {}", lobby.name),
+        format!("map {}", map_state),
+        format!("players {}/{}", clients.len(), server_settings.max_clients),
+        format!("loading {}", clients_load_state.loading_usernames().len()),
+        format!("uptime {:.0}s", uptime),
+        format!("sent {:.0} B/s, received {:.0} B/s", sent, received),
+    ];
+    if let Some(time_left) = round_timer.time_left() {
+        lines.push(format!("next map in {:.0}s", time_left));
+    }
+    lines.join("\n")
+}
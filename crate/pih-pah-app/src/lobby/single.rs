@@ -1,5 +1,5 @@
 use crate::actor::UnloadActorsEvent;
-use crate::character::{spawn_character, spawn_tied_camera, TiedCamera};
+use crate::character::{spawn_character, spawn_tied_camera, TiedCamera, Weapons};
 use crate::component::{DespawnReason, Respawn};
 use crate::lobby::host::generate_player_color;
 use crate::lobby::LobbyState;
@@ -15,6 +15,7 @@ use bevy::hierarchy::DespawnRecursiveExt;
 use bevy::prelude::{in_state, Commands, IntoSystemConfigs, OnEnter};
 use log::info;
 
+use super::clock::GameClock;
 use super::{ChangeMapLobbyEvent, Character, MapLoaderState, PlayerId};
 
 pub struct SingleLobbyPlugins;
@@ -32,7 +33,10 @@ impl Plugin for SingleLobbyPlugins {
     }
 }
 
-fn setup(mut map_events: ResMut<Events<ChangeMapLobbyEvent>>) {
+fn setup(mut commands: Commands, mut map_events: ResMut<Events<ChangeMapLobbyEvent>>) {
+    // respawns are timed by the game clock, in a single game it is the local time
+    commands.init_resource::<GameClock>();
+    commands.insert_resource(Weapons::load());
     map_events.send(ChangeMapLobbyEvent(MapState::ShootingRange));
 }
 
@@ -90,6 +94,8 @@ fn teardown(
     if let Ok(entity) = char_query.get_single() {
         commands.entity(entity).despawn_recursive();
     }
+    commands.remove_resource::<GameClock>();
+    commands.remove_resource::<Weapons>();
 
     unload_actors_event.send(UnloadActorsEvent);
 }
@@ -1,4 +1,5 @@
 use std::env;
+use std::path::Path;
 
 use bevy::prelude::*;
 use bevy::window::{PresentMode, WindowResolution};
@@ -6,6 +7,8 @@ use bevy::winit::WinitWindows;
 use bevy_egui::EguiPlugin;
 use bevy_inspector_egui::DefaultInspectorConfigPlugin;
 use bevy_xpbd_3d::prelude::PhysicsPlugins;
+use pih_pah_app::lobby::match_log::MatchSummary;
+use pih_pah_app::map::ASSET_FOLDER;
 use pih_pah_app::world::WorldPlugins;
 use winit::window::Icon;
 
@@ -17,7 +20,23 @@ fn main() {
 
     env_logger::init();
     info!("Starting pih-pah");
-    let _args: Vec<String> = std::env::args().collect();
+    let args: Vec<String> = std::env::args().collect();
+
+    // pih-pah --match-summary <file> prints the summary of a match history file
+    if let Some(index) = args.iter().position(|arg| arg == "--match-summary") {
+        let Some(path) = args.get(index + 1) else {
+            error!("--match-summary needs a match history file");
+            std::process::exit(1);
+        };
+        match MatchSummary::read(Path::new(path)) {
+            Ok(summary) => print!("{}", summary),
+            Err(err) => {
+                error!("Failed to read match history {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     let is_debug = std::env::var("DEBUG").is_ok();
 
@@ -26,7 +45,7 @@ fn main() {
     if !is_debug {
         app.add_plugins((
             DefaultPlugins.set(AssetPlugin {
-                file_path: "asset".into(),
+                file_path: ASSET_FOLDER.into(),
                 ..default()
             }),
             EguiPlugin,
@@ -47,7 +66,7 @@ fn main() {
         };
         app.add_plugins((
             DefaultPlugins.set(window_plugin_override).set(AssetPlugin {
-                file_path: "asset".into(),
+                file_path: ASSET_FOLDER.into(),
                 ..default()
             }),
             DefaultInspectorConfigPlugin,
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io;
+
+use crate::{lobby::MapLoaderState, world::PromisedScene};
+use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
+use bevy_xpbd_3d::math::PI;
+
+use super::{asset_root, open_map_asset, spawn_point::SpawnPoint, LocalMapFile, MapState};
+
+/// Folder inside [`ASSET_FOLDER`](super::ASSET_FOLDER) with custom maps the host can run.
+pub const CUSTOM_MAP_FOLDER: &str = "maps";
+
+/// The map file loaded by [`MapState::Custom`].
+#[derive(Debug, Default, Resource)]
+pub struct CustomMap(pub Option<LocalMapFile>);
+
+/// Hash of the custom map that is loaded right now.
+#[derive(Debug, Default, Resource)]
+struct LoadedCustomMap(Option<String>);
+
+#[derive(Component)]
+struct Affiliation;
+
+pub struct CustomMapPlugins;
+
+impl Plugin for CustomMapPlugins {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CustomMap>()
+            .init_resource::<LoadedCustomMap>()
+            .add_systems(OnEnter(MapState::Custom), load)
+            .add_systems(OnExit(MapState::Custom), unload)
+            .add_systems(Update, reload.run_if(in_state(MapState::Custom)));
+    }
+}
+
+/// Returns sorted file names of the custom maps the host can run.
+pub fn custom_maps() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(asset_root().join(CUSTOM_MAP_FOLDER))
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| name.ends_with(".glb"))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Reads and hashes a custom map from [`CUSTOM_MAP_FOLDER`].
+pub fn open_custom_map(name: &str) -> io::Result<LocalMapFile> {
+    open_map_asset(&format!("{}/{}", CUSTOM_MAP_FOLDER, name))
+}
+
+fn load(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    custom_map: Res<CustomMap>,
+    mut loaded_custom_map: ResMut<LoadedCustomMap>,
+) {
+    spawn_map(
+        &mut commands,
+        &asset_server,
+        &custom_map,
+        &mut loaded_custom_map,
+    );
+}
+
+fn unload(
+    mut commands: Commands,
+    affiliation_query: Query<Entity, With<Affiliation>>,
+    mut loaded_custom_map: ResMut<LoadedCustomMap>,
+    mut next_state_map: ResMut<NextState<MapLoaderState>>,
+) {
+    despawn_map(
+        &mut commands,
+        &affiliation_query,
+        &mut loaded_custom_map,
+        &mut next_state_map,
+    );
+}
+
+/// Replaces the loaded map if [`CustomMap`] has been changed to another file
+/// without leaving [`MapState::Custom`].
+fn reload(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    custom_map: Res<CustomMap>,
+    mut loaded_custom_map: ResMut<LoadedCustomMap>,
+    affiliation_query: Query<Entity, With<Affiliation>>,
+    mut next_state_map: ResMut<NextState<MapLoaderState>>,
+) {
+    let hash = custom_map.0.as_ref().map(|local| local.file.hash.clone());
+    if hash == loaded_custom_map.0 {
+        return;
+    }
+
+    despawn_map(
+        &mut commands,
+        &affiliation_query,
+        &mut loaded_custom_map,
+        &mut next_state_map,
+    );
+    spawn_map(
+        &mut commands,
+        &asset_server,
+        &custom_map,
+        &mut loaded_custom_map,
+    );
+}
+
+fn spawn_map(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    custom_map: &Res<CustomMap>,
+    loaded_custom_map: &mut ResMut<LoadedCustomMap>,
+) {
+    let Some(local) = custom_map.0.as_ref() else {
+        error!("Custom map is not selected");
+        return;
+    };
+
+    commands
+        .spawn(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: Color::WHITE,
+                illuminance: 4000.0,
+                shadows_enabled: true,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 200.0, 0.0),
+                rotation: Quat::from_rotation_x(PI * 1.5),
+                ..default()
+            },
+            cascade_shadow_config: CascadeShadowConfigBuilder {
+                first_cascade_far_bound: 4.0,
+                ..default()
+            }
+            .into(),
+            ..default()
+        })
+        .insert(Affiliation);
+
+    let scene = asset_server.load(format!("{}#Scene0", local.asset_path));
+
+    commands.spawn((
+        SceneBundle { scene, ..default() },
+        PromisedScene,
+        Affiliation,
+        Name::new(local.file.name.clone()),
+    ));
+
+    commands.insert_resource(SpawnPoint::new(Vec3::new(0., 30., 0.)));
+    loaded_custom_map.0 = Some(local.file.hash.clone());
+}
+
+fn despawn_map(
+    commands: &mut Commands,
+    affiliation_query: &Query<Entity, With<Affiliation>>,
+    loaded_custom_map: &mut ResMut<LoadedCustomMap>,
+    next_state_map: &mut ResMut<NextState<MapLoaderState>>,
+) {
+    for entity in affiliation_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands.insert_resource(SpawnPoint::empty());
+    loaded_custom_map.0 = None;
+    next_state_map.set(MapLoaderState::No);
+}
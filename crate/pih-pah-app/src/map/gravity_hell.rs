@@ -3,7 +3,7 @@ use std::f32::consts::PI;
 use crate::{lobby::MapLoaderState, world::PromisedScene};
 use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
 
-use super::{spawn_point::SpawnPoint, MapState};
+use super::{builtin_map_scene, spawn_point::SpawnPoint, MapOverrides, MapState};
 
 #[derive(Component)]
 struct Affiliation;
@@ -17,7 +17,7 @@ impl Plugin for GravityHellPlugins {
     }
 }
 
-fn load(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn load(mut commands: Commands, asset_server: Res<AssetServer>, map_overrides: Res<MapOverrides>) {
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
             color: Color::WHITE,
@@ -38,7 +38,7 @@ fn load(mut commands: Commands, asset_server: Res<AssetServer>) {
         ..default()
     });
 
-    let scene = asset_server.load("gravity_hell.glb#Scene0");
+    let scene = asset_server.load(builtin_map_scene(MapState::GravityHell, &map_overrides));
 
     commands.spawn((
         SceneBundle { scene, ..default() },
@@ -1,12 +1,14 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use crate::map::menu::MenuPlugins;
 use crate::map::ShootingRangePlugins;
+use crate::world::PromisedScene;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use super::spawn_point::SpawnPoint;
-use super::GravityHellPlugins;
+use super::spawn_point::{SpawnPoint, TeamSpawn, TeamSpawnPoints};
+use super::{CustomMapPlugins, GravityHellPlugins, MapOverrides};
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States, Serialize, Deserialize)]
 pub enum MapState {
@@ -14,6 +16,8 @@ pub enum MapState {
     Menu = 0,
     ShootingRange = 1,
     GravityHell = 2,
+    /// A map from a glb file, see [`CustomMap`](super::CustomMap).
+    Custom = 3,
 }
 
 impl Display for MapState {
@@ -22,6 +26,21 @@ impl Display for MapState {
             MapState::Menu => write!(f, "Menu"),
             MapState::ShootingRange => write!(f, "ShootingRange"),
             MapState::GravityHell => write!(f, "GravityHell"),
+            MapState::Custom => write!(f, "Custom"),
+        }
+    }
+}
+
+impl FromStr for MapState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Menu" => Ok(MapState::Menu),
+            "ShootingRange" => Ok(MapState::ShootingRange),
+            "GravityHell" => Ok(MapState::GravityHell),
+            "Custom" => Ok(MapState::Custom),
+            _ => Err(format!("Unknown map {}", s)),
         }
     }
 }
@@ -30,12 +49,49 @@ pub fn is_loaded(spawn_point: &Res<SpawnPoint>) -> bool {
     !spawn_point.is_empty()
 }
 
+/// Returns `true` if the map has a spawn point and all of its scenes have been processed.
+pub fn is_ready(
+    spawn_point: &Res<SpawnPoint>,
+    promised_scene_query: &Query<(), With<PromisedScene>>,
+) -> bool {
+    is_loaded(spawn_point) && promised_scene_query.is_empty()
+}
+
 pub struct MapPlugins;
 
 impl Plugin for MapPlugins {
     fn build(&self, app: &mut App) {
         app.add_state::<MapState>()
             .init_resource::<SpawnPoint>()
-            .add_plugins((MenuPlugins, ShootingRangePlugins, GravityHellPlugins));
+            .init_resource::<TeamSpawnPoints>()
+            .init_resource::<MapOverrides>()
+            .add_plugins((
+                MenuPlugins,
+                ShootingRangePlugins,
+                GravityHellPlugins,
+                CustomMapPlugins,
+            ))
+            .add_systems(
+                Update,
+                (
+                    clear_team_spawn_points.run_if(state_changed::<MapState>()),
+                    collect_team_spawn_points,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn clear_team_spawn_points(mut team_spawn_points: ResMut<TeamSpawnPoints>) {
+    team_spawn_points.clear();
+}
+
+/// Adds the team spawn points found in the map scene to [`TeamSpawnPoints`].
+fn collect_team_spawn_points(
+    query: Query<(&TeamSpawn, &GlobalTransform), Added<TeamSpawn>>,
+    mut team_spawn_points: ResMut<TeamSpawnPoints>,
+) {
+    for (TeamSpawn(team), transform) in query.iter() {
+        team_spawn_points.insert(*team, transform.translation());
     }
 }
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::util::hash::content_hash;
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{CustomMap, MapState};
+
+/// Folder with game assets, relative to the executable.
+pub const ASSET_FOLDER: &str = "asset";
+/// Folder inside [`ASSET_FOLDER`] with maps downloaded from hosts, named by their hash.
+pub const MAP_CACHE_FOLDER: &str = "map_cache";
+
+/// A map glb file shared between host and clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapFile {
+    /// File name on the host.
+    pub name: String,
+    /// Hash of the file content ([`content_hash`]).
+    pub hash: String,
+    /// File size in bytes.
+    pub size: u64,
+}
+
+/// A [`MapFile`] available on this machine.
+#[derive(Debug, Clone)]
+pub struct LocalMapFile {
+    pub file: MapFile,
+    /// Path to the file inside [`ASSET_FOLDER`].
+    pub asset_path: String,
+}
+
+/// Files downloaded from the host that replace the local files of built-in maps,
+/// because the host has other versions of them.
+#[derive(Debug, Default, Resource)]
+pub struct MapOverrides(pub HashMap<MapState, LocalMapFile>);
+
+/// Returns the absolute path of [`ASSET_FOLDER`].
+pub fn asset_root() -> PathBuf {
+    FileAssetReader::get_base_path().join(ASSET_FOLDER)
+}
+
+/// Returns the path inside [`ASSET_FOLDER`] of the glb file shipped with a built-in map.
+pub fn builtin_map_asset(map_state: MapState) -> Option<&'static str> {
+    match map_state {
+        MapState::ShootingRange => Some("test_map.glb"),
+        MapState::GravityHell => Some("gravity_hell.glb"),
+        MapState::Menu | MapState::Custom => None,
+    }
+}
+
+/// Returns the scene of a built-in map to load,
+/// the downloaded file from [`MapOverrides`] if there is one.
+pub fn builtin_map_scene(map_state: MapState, map_overrides: &MapOverrides) -> String {
+    let asset_path = match map_overrides.0.get(&map_state) {
+        Some(local) => local.asset_path.clone(),
+        None => builtin_map_asset(map_state).unwrap_or_default().to_string(),
+    };
+    format!("{}#Scene0", asset_path)
+}
+
+/// Reads and hashes a map file from [`ASSET_FOLDER`].
+pub fn open_map_asset(asset_path: &str) -> io::Result<LocalMapFile> {
+    let data = fs::read(asset_root().join(asset_path))?;
+    let name = Path::new(asset_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| asset_path.to_string());
+
+    Ok(LocalMapFile {
+        file: MapFile {
+            name,
+            hash: content_hash(&data),
+            size: data.len() as u64,
+        },
+        asset_path: asset_path.to_string(),
+    })
+}
+
+/// Returns the file this machine loads for the map, `None` for maps without a file.
+pub fn local_map_file(
+    map_state: MapState,
+    custom_map: &CustomMap,
+    map_overrides: &MapOverrides,
+) -> Option<LocalMapFile> {
+    if map_state == MapState::Custom {
+        return custom_map.0.clone();
+    }
+    if let Some(local) = map_overrides.0.get(&map_state) {
+        return Some(local.clone());
+    }
+
+    let asset_path = builtin_map_asset(map_state)?;
+    match open_map_asset(asset_path) {
+        Ok(local) => Some(local),
+        Err(err) => {
+            error!("Failed to read map {}: {}", asset_path, err);
+            None
+        }
+    }
+}
+
+/// Returns the map from [`MAP_CACHE_FOLDER`] if it has been downloaded before and is intact.
+pub fn cached_map(file: &MapFile) -> Option<LocalMapFile> {
+    let asset_path = format!("{}/{}.glb", MAP_CACHE_FOLDER, file.hash);
+    let data = fs::read(asset_root().join(&asset_path)).ok()?;
+    if content_hash(&data) != file.hash {
+        warn!("Cached map {} is damaged", asset_path);
+        return None;
+    }
+
+    Some(LocalMapFile {
+        file: file.clone(),
+        asset_path,
+    })
+}
+
+/// Stores a downloaded map in [`MAP_CACHE_FOLDER`].
+pub fn cache_map(file: &MapFile, data: &[u8]) -> io::Result<LocalMapFile> {
+    let asset_path = format!("{}/{}.glb", MAP_CACHE_FOLDER, file.hash);
+    fs::create_dir_all(asset_root().join(MAP_CACHE_FOLDER))?;
+    fs::write(asset_root().join(&asset_path), data)?;
+
+    Ok(LocalMapFile {
+        file: file.clone(),
+        asset_path,
+    })
+}
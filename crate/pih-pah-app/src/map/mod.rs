@@ -1,13 +1,17 @@
 #![allow(clippy::module_inception)]
 
+mod custom;
 mod gravity_hell;
 mod map;
+mod map_file;
 mod menu;
 mod shooting_range;
 mod spawn_point;
 
+pub use custom::*;
 pub use gravity_hell::*;
 pub use map::*;
+pub use map_file::*;
 pub use menu::*;
 pub use shooting_range::*;
 pub use spawn_point::*;
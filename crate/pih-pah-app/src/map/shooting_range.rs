@@ -2,7 +2,7 @@ use crate::{lobby::MapLoaderState, world::PromisedScene};
 use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
 use bevy_xpbd_3d::math::PI;
 
-use super::{spawn_point::SpawnPoint, MapState};
+use super::{builtin_map_scene, spawn_point::SpawnPoint, MapOverrides, MapState};
 
 #[derive(Component)]
 struct Affiliation;
@@ -16,7 +16,7 @@ impl Plugin for ShootingRangePlugins {
     }
 }
 
-fn load(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn load(mut commands: Commands, asset_server: Res<AssetServer>, map_overrides: Res<MapOverrides>) {
     commands
         .spawn(DirectionalLightBundle {
             directional_light: DirectionalLight {
@@ -39,7 +39,7 @@ fn load(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(Affiliation);
 
-    let scene = asset_server.load("test_map.glb#Scene0");
+    let scene = asset_server.load(builtin_map_scene(MapState::ShootingRange, &map_overrides));
 
     commands.spawn((
         SceneBundle { scene, ..default() },
@@ -1,6 +1,15 @@
-use bevy::{ecs::system::Resource, math::Vec3, prelude::Deref, reflect::Reflect};
+use std::collections::HashMap;
+
+use bevy::{
+    ecs::{component::Component, system::Resource},
+    math::Vec3,
+    prelude::Deref,
+    reflect::Reflect,
+};
 use rand::Rng;
 
+use crate::mode::Team;
+
 #[derive(Debug, Clone, Resource, Deref, Default, Reflect)]
 pub struct SpawnPoint(Vec<Vec3>);
 
@@ -28,6 +37,33 @@ impl SpawnPoint {
     }
 }
 
+/// Spawn points of the teams, from the `t:<team>` name tags of the map scene.
+///
+/// A team without spawn points uses the common [`SpawnPoint`].
+#[derive(Debug, Default, Resource)]
+pub struct TeamSpawnPoints(HashMap<Team, Vec<Vec3>>);
+
+impl TeamSpawnPoints {
+    pub fn get(&self, team: Team) -> Option<SpawnPoint> {
+        self.0
+            .get(&team)
+            .filter(|points| !points.is_empty())
+            .map(|points| SpawnPoint(points.clone()))
+    }
+
+    pub fn insert(&mut self, team: Team, point: Vec3) {
+        self.0.entry(team).or_default().push(point);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Marks a spawn point of a team in the map scene, see [`TeamSpawnPoints`].
+#[derive(Debug, Clone, Copy, Component)]
+pub struct TeamSpawn(pub Team);
+
 pub trait IntoVec3Vec {
     fn into_vec3_vec(self) -> Vec<Vec3>;
 }
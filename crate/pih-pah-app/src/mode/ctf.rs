@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+use renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use crate::character::Dead;
+use crate::component::RespawnEvent;
+use crate::lobby::clock::GameClock;
+use crate::lobby::host::{ClientsLoadState, SyncedClients};
+use crate::lobby::{Character, Lobby, PlayerId, ServerMessages};
+use crate::world::LinkId;
+
+use super::{RoundRestartEvent, Scoreboard, Team};
+
+/// Distance from a flag within which characters touch it.
+pub const FLAG_REACH: f32 = 2.;
+
+/// Height of a carried flag above its carrier.
+const CARRY_HEIGHT: f32 = 2.;
+
+/// Seconds a dropped flag lies on the ground before it returns to its base.
+pub const FLAG_RETURN_TIME: f64 = 20.;
+
+/// Where a [`Flag`] is, replicated to the clients with [`ServerMessages::FlagState`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FlagState {
+    /// The flag stands at its base.
+    Home,
+    /// A player of the other team carries the flag.
+    Carried(PlayerId),
+    /// The flag lies where its carrier has died.
+    ///
+    /// * `position` - World position of the flag.
+    /// * `return_at` - Server timestamp of the return to the base, see [`GameClock`].
+    Dropped { position: Vec3, return_at: f64 },
+}
+
+/// A flag of a team in capture the flag, placed with the `f:<team>` name tag in the map scene.
+///
+/// The flag node should have no collider, it is moved by its state and not by physics.
+#[derive(Debug, Component)]
+pub struct Flag {
+    pub team: Team,
+    pub state: FlagState,
+}
+
+impl Flag {
+    pub fn new(team: Team) -> Self {
+        Self {
+            team,
+            state: FlagState::Home,
+        }
+    }
+
+    /// Returns the link of the flag of the team, snapshots move flags like other scene props.
+    pub fn link_id(team: Team) -> LinkId {
+        LinkId::Scene(format!("flag_{}", team.name().to_lowercase()))
+    }
+}
+
+/// The base of a [`Flag`]: its parent and transform in the map scene.
+#[derive(Debug, Component)]
+pub struct FlagHome {
+    parent: Option<Entity>,
+    transform: Transform,
+}
+
+/// Remembers the bases of the flags of a new map.
+pub fn init_flags(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, Option<&Parent>), Added<Flag>>,
+) {
+    for (entity, transform, parent) in query.iter() {
+        commands.entity(entity).insert(FlagHome {
+            parent: parent.map(Parent::get),
+            transform: *transform,
+        });
+    }
+}
+
+fn set_flag_parent(commands: &mut Commands, flag: Entity, parent: Option<Entity>) {
+    match parent {
+        Some(parent) => commands.entity(flag).set_parent(parent),
+        None => commands.entity(flag).remove_parent(),
+    };
+}
+
+/// Moves flags to their base, above their carrier or where they have been dropped
+/// when their state changes.
+pub fn place_flags(
+    mut commands: Commands,
+    query: Query<(Entity, &Flag, &FlagHome), Changed<Flag>>,
+    global_query: Query<&GlobalTransform>,
+    lobby: Res<Lobby>,
+) {
+    for (entity, flag, home) in query.iter() {
+        match flag.state {
+            FlagState::Home => {
+                set_flag_parent(&mut commands, entity, home.parent);
+                commands.entity(entity).insert(home.transform);
+            }
+            FlagState::Carried(player_id) => {
+                let Some(player_data) = lobby.players.get(&player_id) else {
+                    continue;
+                };
+                commands
+                    .entity(entity)
+                    .set_parent(player_data.entity)
+                    .insert(Transform::from_translation(Vec3::Y * CARRY_HEIGHT));
+            }
+            FlagState::Dropped { position, .. } => {
+                let translation = home
+                    .parent
+                    .and_then(|parent| global_query.get(parent).ok())
+                    .map_or(position, |parent| {
+                        parent.affine().inverse().transform_point3(position)
+                    });
+                set_flag_parent(&mut commands, entity, home.parent);
+                commands
+                    .entity(entity)
+                    .insert(Transform::from_translation(translation));
+            }
+        }
+    }
+}
+
+/// Takes the flags off a character that is about to be despawned, they stay where they are
+/// until the host drops them.
+///
+/// `flags` are flag entities with their parents.
+pub fn detach_flags(
+    commands: &mut Commands,
+    flags: impl IntoIterator<Item = (Entity, Entity)>,
+    carrier: Entity,
+) {
+    for (flag, parent) in flags {
+        if parent == carrier {
+            commands.entity(flag).remove_parent_in_place();
+        }
+    }
+}
+
+/// Removes the flags carried from the previous map, the new map has its own flags.
+pub fn clear_carried_flags(mut commands: Commands, query: Query<(Entity, &Flag)>) {
+    for (entity, flag) in query.iter() {
+        if let FlagState::Carried(_) = flag.state {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Picks up flags touched by the other team, returns dropped flags touched by their team
+/// and scores a capture when a carrier touches the flag of its team at its base.
+pub fn touch_flags(
+    mut flag_query: Query<(&mut Flag, &GlobalTransform)>,
+    character_query: Query<(&Character, &GlobalTransform), Without<Dead>>,
+    lobby: Res<Lobby>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    if scoreboard.is_over() {
+        return;
+    }
+
+    let mut captures = Vec::new();
+    for (character, transform) in character_query.iter() {
+        let Some(team) = lobby
+            .players
+            .get(&character.id)
+            .and_then(|player_data| player_data.team)
+        else {
+            continue;
+        };
+        let carried = flag_query
+            .iter()
+            .find(|(flag, _)| flag.state == FlagState::Carried(character.id))
+            .map(|(flag, _)| flag.team);
+
+        for (mut flag, flag_transform) in flag_query.iter_mut() {
+            if flag_transform
+                .translation()
+                .distance(transform.translation())
+                > FLAG_REACH
+            {
+                continue;
+            }
+            match flag.state {
+                FlagState::Carried(_) => {}
+                FlagState::Home | FlagState::Dropped { .. } if flag.team != team => {
+                    flag.state = FlagState::Carried(character.id);
+                }
+                FlagState::Dropped { .. } => flag.state = FlagState::Home,
+                FlagState::Home => {
+                    if let Some(carried) = carried {
+                        captures.push((character.id, team, carried));
+                    }
+                }
+            }
+        }
+    }
+
+    for (player_id, team, carried) in captures {
+        for (mut flag, _) in flag_query.iter_mut() {
+            if flag.team == carried {
+                flag.state = FlagState::Home;
+            }
+        }
+        *scoreboard.teams.entry(team).or_default() += 1;
+        if let Some(player) = scoreboard.players.get_mut(&player_id) {
+            player.score += 1;
+        }
+    }
+}
+
+/// Drops flags whose carriers have died, left or joined the team of the flag,
+/// and returns dropped flags to their base after [`FLAG_RETURN_TIME`].
+///
+/// A carrier respawned without dying, e.g. after falling off the map, returns the flag
+/// to its base, otherwise the carrier would bring it next to their own base.
+pub fn drop_flags(
+    mut flag_query: Query<(&mut Flag, &GlobalTransform)>,
+    mut respawn_event: EventReader<RespawnEvent>,
+    alive_query: Query<(), Without<Dead>>,
+    lobby: Res<Lobby>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    let respawned: Vec<Entity> = respawn_event.read().map(|event| event.entity).collect();
+    let now = clock.now(&time);
+    for (mut flag, transform) in flag_query.iter_mut() {
+        match flag.state {
+            FlagState::Carried(player_id) => {
+                let respawned = lobby
+                    .players
+                    .get(&player_id)
+                    .is_some_and(|player_data| respawned.contains(&player_data.entity));
+                if respawned {
+                    flag.state = FlagState::Home;
+                    continue;
+                }
+
+                let carried = lobby.players.get(&player_id).is_some_and(|player_data| {
+                    player_data.team != Some(flag.team) && alive_query.contains(player_data.entity)
+                });
+                if !carried {
+                    flag.state = FlagState::Dropped {
+                        position: transform.translation(),
+                        return_at: now + FLAG_RETURN_TIME,
+                    };
+                }
+            }
+            FlagState::Dropped { return_at, .. } if now >= return_at => {
+                flag.state = FlagState::Home;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns every flag to its base when the round restarts on the same map.
+pub fn return_flags(
+    mut round_restart_event: EventReader<RoundRestartEvent>,
+    mut flag_query: Query<&mut Flag>,
+) {
+    if round_restart_event.read().count() == 0 {
+        return;
+    }
+    for mut flag in flag_query.iter_mut() {
+        if flag.state != FlagState::Home {
+            flag.state = FlagState::Home;
+        }
+    }
+}
+
+/// Sends the state of each flag that has been picked up, dropped or returned.
+pub fn send_flags(
+    mut server: ResMut<RenetServer>,
+    mut synced_clients: Local<SyncedClients>,
+    flag_query: Query<Ref<Flag>>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    let targets = synced_clients.sync(&clients_load_state);
+    for flag in flag_query.iter() {
+        targets.send(&mut server, flag.is_changed(), || {
+            ServerMessages::FlagState {
+                team: flag.team,
+                state: flag.state,
+            }
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::ecs::event::EventReader;
+use bevy::ecs::system::ResMut;
+
+use crate::character::DeathEvent;
+
+use super::Scoreboard;
+
+/// Counts kills and deaths of the round.
+///
+/// In deathmatch modes a kill gives the killer a point, in team deathmatch also its team.
+/// Killing yourself or a teammate costs a point.
+pub fn score_kills(mut death_event: EventReader<DeathEvent>, mut scoreboard: ResMut<Scoreboard>) {
+    for DeathEvent { victim, killer, .. } in death_event.read() {
+        if scoreboard.is_over() {
+            continue;
+        }
+        let scoreboard = &mut *scoreboard;
+        let scores_kills = scoreboard.mode.scores_kills();
+
+        let Some(victim_score) = scoreboard.players.get_mut(victim) else {
+            continue;
+        };
+        victim_score.deaths += 1;
+        let victim_team = victim_score.team;
+
+        let killer = killer.filter(|killer| killer != victim);
+        let Some(killer_score) = killer.and_then(|killer| scoreboard.players.get_mut(&killer))
+        else {
+            if scores_kills {
+                if let Some(victim_score) = scoreboard.players.get_mut(victim) {
+                    victim_score.score -= 1;
+                }
+                if let Some(team) = victim_team {
+                    *scoreboard.teams.entry(team).or_default() -= 1;
+                }
+            }
+            continue;
+        };
+        killer_score.kills += 1;
+        if !scores_kills {
+            continue;
+        }
+
+        let points = match killer_score.team {
+            Some(team) if Some(team) == victim_team => -1,
+            _ => 1,
+        };
+        killer_score.score += points;
+        if let Some(team) = killer_score.team {
+            *scoreboard.teams.entry(team).or_default() += points;
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
+use renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use crate::character::Dead;
+use crate::lobby::host::{ClientsLoadState, SyncedClients};
+use crate::lobby::{Character, Lobby, PlayerId, ServerMessages};
+
+use super::{RoundRestartEvent, Scoreboard, Team};
+
+/// Seconds a team holds a control zone for one point.
+pub const ZONE_SCORE_INTERVAL: f32 = 1.;
+
+/// Who is in a [`ControlZone`], replicated to the clients with [`ServerMessages::ZoneState`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ZoneState {
+    /// Nobody is in the zone.
+    Neutral,
+    /// Only players of the team are in the zone, the team scores for it.
+    Held(Team),
+    /// Players of both teams are in the zone, nobody scores until one team is left.
+    Contested,
+}
+
+/// A control point of king of the hill, placed with the `z:<name>` name tag on a mesh
+/// of the map scene.
+///
+/// The bounds of the mesh are the trigger volume, so the node should have no collider,
+/// and a transparent material lets players see who holds the zone.
+#[derive(Debug, Component)]
+pub struct ControlZone {
+    pub name: String,
+    pub state: ZoneState,
+}
+
+impl ControlZone {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            state: ZoneState::Neutral,
+        }
+    }
+
+    /// Returns `true` if the point in world space is inside the zone.
+    fn contains(transform: &GlobalTransform, aabb: &Aabb, point: Vec3) -> bool {
+        let local = transform.affine().inverse().transform_point3(point);
+        (local - Vec3::from(aabb.center))
+            .abs()
+            .cmple(aabb.half_extents.into())
+            .all()
+    }
+}
+
+/// Updates who holds the zones and gives the holding team and its players in the zone
+/// a point for every [`ZONE_SCORE_INTERVAL`] they hold it.
+///
+/// A contested zone keeps the time its holder has already gathered, the time is lost
+/// when the zone is left or taken by the other team.
+pub fn hold_zones(
+    mut zone_query: Query<(Entity, &mut ControlZone, &GlobalTransform, &Aabb)>,
+    character_query: Query<(&Character, &GlobalTransform), Without<Dead>>,
+    lobby: Res<Lobby>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut hold_times: Local<HashMap<Entity, (Team, f32)>>,
+    time: Res<Time>,
+) {
+    hold_times.retain(|entity, _| zone_query.contains(*entity));
+    if scoreboard.is_over() {
+        return;
+    }
+
+    for (entity, mut zone, transform, aabb) in zone_query.iter_mut() {
+        let occupants: Vec<(PlayerId, Team)> = character_query
+            .iter()
+            .filter(|(_, character_transform)| {
+                ControlZone::contains(transform, aabb, character_transform.translation())
+            })
+            .filter_map(|(character, _)| {
+                let team = lobby.players.get(&character.id)?.team?;
+                Some((character.id, team))
+            })
+            .collect();
+
+        let state = match occupants.first() {
+            None => ZoneState::Neutral,
+            Some((_, team)) if occupants.iter().all(|(_, other)| other == team) => {
+                ZoneState::Held(*team)
+            }
+            Some(_) => ZoneState::Contested,
+        };
+        if zone.state != state {
+            zone.state = state;
+        }
+
+        let team = match state {
+            ZoneState::Held(team) => team,
+            ZoneState::Contested => continue,
+            ZoneState::Neutral => {
+                hold_times.remove(&entity);
+                continue;
+            }
+        };
+        let (holder, hold_time) = hold_times.entry(entity).or_insert((team, 0.));
+        if *holder != team {
+            *holder = team;
+            *hold_time = 0.;
+        }
+        *hold_time += time.delta_seconds();
+        while *hold_time >= ZONE_SCORE_INTERVAL {
+            *hold_time -= ZONE_SCORE_INTERVAL;
+            *scoreboard.teams.entry(team).or_default() += 1;
+            for (player_id, _) in occupants.iter() {
+                if let Some(player) = scoreboard.players.get_mut(player_id) {
+                    player.score += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Frees every zone when the round restarts on the same map.
+pub fn reset_zones(
+    mut round_restart_event: EventReader<RoundRestartEvent>,
+    mut zone_query: Query<&mut ControlZone>,
+) {
+    if round_restart_event.read().count() == 0 {
+        return;
+    }
+    for mut zone in zone_query.iter_mut() {
+        if zone.state != ZoneState::Neutral {
+            zone.state = ZoneState::Neutral;
+        }
+    }
+}
+
+/// Sends the state of each zone that has been taken, contested or left.
+pub fn send_zones(
+    mut server: ResMut<RenetServer>,
+    mut synced_clients: Local<SyncedClients>,
+    zone_query: Query<Ref<ControlZone>>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    let targets = synced_clients.sync(&clients_load_state);
+    for zone in zone_query.iter() {
+        targets.send(&mut server, zone.is_changed(), || {
+            ServerMessages::ZoneState {
+                name: zone.name.clone(),
+                state: zone.state,
+            }
+        });
+    }
+}
+
+/// Colors the zones by their state, keeping the transparency of their material.
+pub fn tint_zones(
+    zone_query: Query<(&ControlZone, &Handle<StandardMaterial>), Changed<ControlZone>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (zone, handle) in zone_query.iter() {
+        let Some(material) = materials.get_mut(handle) else {
+            continue;
+        };
+        let color = match zone.state {
+            ZoneState::Neutral => Color::WHITE,
+            ZoneState::Held(team) => team.color(),
+            ZoneState::Contested => Color::YELLOW,
+        };
+        material.base_color = color.with_a(material.base_color.a());
+    }
+}
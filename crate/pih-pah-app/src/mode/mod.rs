@@ -0,0 +1,13 @@
+#![allow(clippy::module_inception)]
+
+mod ctf;
+mod deathmatch;
+mod koth;
+mod mode;
+mod team;
+
+pub use ctf::*;
+pub use deathmatch::*;
+pub use koth::*;
+pub use mode::*;
+pub use team::*;
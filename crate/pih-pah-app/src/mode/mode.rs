@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use crate::component::{DespawnReason, Respawn};
+use crate::lobby::clock::GameClock;
+use crate::lobby::host::{ClientsLoadState, SyncedClients};
+use crate::lobby::map_rotation::MapRotation;
+use crate::lobby::match_log::{MatchEvent, MatchLogEvent};
+use crate::lobby::{
+    ChangeMapLobbyEvent, Character, HostResource, Lobby, LobbyState, MapLoaderState, PlayerId,
+    ServerMessages,
+};
+use crate::map::{CustomMap, MapState};
+use crate::settings::ServerSettings;
+
+use super::{
+    apply_team_spawn_points, balance_teams, clear_carried_flags, drop_flags, hold_zones,
+    init_flags, place_flags, request_team_switch_client, request_team_switch_host, reset_zones,
+    return_flags, score_kills, send_flags, send_zones, switch_teams, tint_characters, tint_zones,
+    touch_flags, FriendlyFire, RequestTeamSwitchEvent, SwitchTeamEvent, Team,
+};
+
+/// Rules of a hosted match, selected by the host when the lobby is created.
+///
+/// The mode decides how players score, when a round is won and when characters respawn,
+/// the [`GameModePlugins`] run the time of the round with the [`RoundTimer`].
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Everyone against everyone, each kill scores for the killer.
+    #[default]
+    Deathmatch,
+    /// Two teams, each kill scores for the team of the killer.
+    TeamDeathmatch,
+    /// Two teams, bringing the flag of the other team to the own flag scores a capture.
+    CaptureTheFlag,
+    /// Two teams, holding the control zones of the map alone scores points over time.
+    KingOfTheHill,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Deathmatch,
+        GameMode::TeamDeathmatch,
+        GameMode::CaptureTheFlag,
+        GameMode::KingOfTheHill,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Deathmatch => "Deathmatch",
+            GameMode::TeamDeathmatch => "Team deathmatch",
+            GameMode::CaptureTheFlag => "Capture the flag",
+            GameMode::KingOfTheHill => "King of the hill",
+        }
+    }
+
+    /// Returns `true` if players of the mode play in [`Team`]s.
+    pub fn has_teams(&self) -> bool {
+        matches!(
+            self,
+            GameMode::TeamDeathmatch | GameMode::CaptureTheFlag | GameMode::KingOfTheHill
+        )
+    }
+
+    /// Returns `true` if kills give points, otherwise they are only counted.
+    pub fn scores_kills(&self) -> bool {
+        matches!(self, GameMode::Deathmatch | GameMode::TeamDeathmatch)
+    }
+
+    /// Returns the seconds a killed character waits for its respawn.
+    pub fn respawn_delay(&self, server_settings: &ServerSettings) -> f32 {
+        match self {
+            GameMode::Deathmatch
+            | GameMode::TeamDeathmatch
+            | GameMode::CaptureTheFlag
+            | GameMode::KingOfTheHill => server_settings.respawn_delay,
+        }
+    }
+
+    /// Returns the score that wins a round of the mode.
+    pub fn score_limit(&self, server_settings: &ServerSettings) -> u32 {
+        match self {
+            GameMode::Deathmatch | GameMode::TeamDeathmatch => server_settings.score_limit,
+            GameMode::CaptureTheFlag => server_settings.capture_limit,
+            GameMode::KingOfTheHill => server_settings.hill_limit,
+        }
+    }
+}
+
+/// Score of one player in the current round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerScore {
+    pub username: String,
+    /// Team of the player, `None` in modes without teams.
+    pub team: Option<Team>,
+    pub kills: u32,
+    pub deaths: u32,
+    pub score: i32,
+}
+
+/// Result of a finished round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundEnd {
+    /// Name of the winning player or team, `None` on a draw.
+    pub winner: Option<String>,
+    /// Server timestamp of the next round, see [`GameClock`].
+    pub restart_at: f64,
+}
+
+/// Scores of the current round, kept by the host and replicated to the clients.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scoreboard {
+    pub mode: GameMode,
+    /// Score that wins the round, `0` if the round only ends by time.
+    pub score_limit: u32,
+    pub players: HashMap<PlayerId, PlayerScore>,
+    pub teams: HashMap<Team, i32>,
+    /// Set when the round is over, until the next round starts.
+    pub round_end: Option<RoundEnd>,
+}
+
+impl Scoreboard {
+    pub fn new(mode: GameMode, score_limit: u32) -> Self {
+        let teams = if mode.has_teams() {
+            Team::ALL.into_iter().map(|team| (team, 0)).collect()
+        } else {
+            HashMap::new()
+        };
+        Self {
+            mode,
+            score_limit,
+            teams,
+            ..default()
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.round_end.is_some()
+    }
+
+    /// Returns the players ordered by their score, the best one first.
+    pub fn ranking(&self) -> Vec<(PlayerId, &PlayerScore)> {
+        let mut ranking: Vec<_> = self
+            .players
+            .iter()
+            .map(|(id, score)| (*id, score))
+            .collect();
+        ranking.sort_by(|(_, a), (_, b)| {
+            b.score
+                .cmp(&a.score)
+                .then(a.deaths.cmp(&b.deaths))
+                .then_with(|| a.username.cmp(&b.username))
+        });
+        ranking
+    }
+
+    /// Returns the name and the score of the best team or player, `None` on a draw.
+    pub fn leader(&self) -> Option<(String, i32)> {
+        let mut scores: Vec<(String, i32)> = if self.mode.has_teams() {
+            self.teams
+                .iter()
+                .map(|(team, score)| (format!("{} team", team.name()), *score))
+                .collect()
+        } else {
+            self.players
+                .values()
+                .map(|player| (player.username.clone(), player.score))
+                .collect()
+        };
+        scores.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        match scores.as_slice() {
+            [first, second, ..] if first.1 == second.1 => None,
+            [first, ..] => Some(first.clone()),
+            [] => None,
+        }
+    }
+
+    /// Clears the scores for a new round, players keep their teams.
+    fn reset(&mut self) {
+        for player in self.players.values_mut() {
+            player.kills = 0;
+            player.deaths = 0;
+            player.score = 0;
+        }
+        for score in self.teams.values_mut() {
+            *score = 0;
+        }
+        self.round_end = None;
+    }
+}
+
+/// Time left in the current round, `None` if the map has no time limit.
+///
+/// The time limit is the one of the current map of the [`MapRotation`],
+/// the timer starts again on every map change and round restart.
+#[derive(Resource, Debug, Default)]
+pub struct RoundTimer(Option<Timer>);
+
+impl RoundTimer {
+    /// Gives the round the full time limit.
+    pub fn restart(&mut self, time_limit: Option<f32>) {
+        self.0 = time_limit.map(|time_limit| Timer::from_seconds(time_limit, TimerMode::Once));
+    }
+
+    /// Returns seconds left in the round.
+    pub fn time_left(&self) -> Option<f32> {
+        self.0.as_ref().map(|timer| timer.remaining_secs())
+    }
+
+    /// Returns `true` if the time of the round is over.
+    pub fn is_time_up(&self) -> bool {
+        self.0.as_ref().is_some_and(Timer::finished)
+    }
+}
+
+/// Sent when a round restarts on the same map, objectives of the map go back to their start.
+#[derive(Debug, Event)]
+pub struct RoundRestartEvent;
+
+pub struct GameModePlugins;
+
+impl Plugin for GameModePlugins {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SwitchTeamEvent>()
+            .add_event::<RequestTeamSwitchEvent>()
+            .add_event::<RoundRestartEvent>()
+            .add_systems(OnEnter(LobbyState::Host), setup)
+            .add_systems(
+                Update,
+                (
+                    (request_team_switch_host, balance_teams, switch_teams).chain(),
+                    apply_team_spawn_points,
+                    sync_score_players,
+                    (restart_round_timer, tick_round_timer).chain(),
+                    score_kills,
+                    end_round,
+                    restart_round,
+                    reset_on_map_change,
+                    send_scoreboard,
+                )
+                    .run_if(in_state(LobbyState::Host).and_then(resource_exists::<Scoreboard>())),
+            )
+            .add_systems(
+                Update,
+                (touch_flags, drop_flags, return_flags, send_flags).run_if(
+                    in_state(LobbyState::Host)
+                        .and_then(resource_exists_and_equals(GameMode::CaptureTheFlag)),
+                ),
+            )
+            .add_systems(
+                Update,
+                (hold_zones, reset_zones, send_zones).run_if(
+                    in_state(LobbyState::Host)
+                        .and_then(resource_exists_and_equals(GameMode::KingOfTheHill)),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    tint_characters,
+                    clear_carried_flags.run_if(state_changed::<MapState>()),
+                    init_flags,
+                    place_flags,
+                    tint_zones,
+                )
+                    .run_if(not(in_state(LobbyState::None)).and_then(resource_exists::<Lobby>())),
+            )
+            .add_systems(
+                Update,
+                request_team_switch_client
+                    .run_if(in_state(LobbyState::Client).and_then(bevy_renet::client_connected())),
+            )
+            .add_systems(OnExit(LobbyState::Host), teardown)
+            .add_systems(OnExit(LobbyState::Client), teardown);
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    host_resource: Res<HostResource>,
+    server_settings: Res<ServerSettings>,
+) {
+    commands.insert_resource(host_resource.game_mode);
+    commands.insert_resource(FriendlyFire(host_resource.friendly_fire));
+    commands.init_resource::<RoundTimer>();
+    commands.insert_resource(Scoreboard::new(
+        host_resource.game_mode,
+        host_resource.game_mode.score_limit(&server_settings),
+    ));
+}
+
+fn teardown(mut commands: Commands) {
+    commands.remove_resource::<GameMode>();
+    commands.remove_resource::<FriendlyFire>();
+    commands.remove_resource::<RoundTimer>();
+    commands.remove_resource::<Scoreboard>();
+}
+
+/// Adds joined players to the scoreboard, removes the ones that have left
+/// and follows the team switches.
+fn sync_score_players(lobby: Res<Lobby>, mut scoreboard: ResMut<Scoreboard>) {
+    if scoreboard
+        .players
+        .keys()
+        .any(|player_id| !lobby.players.contains_key(player_id))
+    {
+        scoreboard
+            .players
+            .retain(|player_id, _| lobby.players.contains_key(player_id));
+    }
+
+    for (player_id, player_data) in lobby.players.iter() {
+        if let Some(player) = scoreboard.players.get(player_id) {
+            if player.team != player_data.team {
+                scoreboard.players.get_mut(player_id).unwrap().team = player_data.team;
+            }
+            continue;
+        }
+        scoreboard.players.insert(
+            *player_id,
+            PlayerScore {
+                username: player_data.username.clone(),
+                team: player_data.team,
+                kills: 0,
+                deaths: 0,
+                score: 0,
+            },
+        );
+    }
+}
+
+/// Ends the round when a player or team reaches the score limit or the time of the map is over.
+fn end_round(
+    mut scoreboard: ResMut<Scoreboard>,
+    mut match_log_event: EventWriter<MatchLogEvent>,
+    round_timer: Res<RoundTimer>,
+    server_settings: Res<ServerSettings>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    if scoreboard.is_over() {
+        return;
+    }
+
+    let leader = scoreboard.leader();
+    let score_reached = scoreboard.score_limit > 0
+        && leader
+            .as_ref()
+            .is_some_and(|(_, score)| *score >= scoreboard.score_limit as i32);
+    if !score_reached && !round_timer.is_time_up() {
+        return;
+    }
+
+    let winner = leader.map(|(name, _)| name);
+    match_log_event.send(MatchLogEvent(MatchEvent::RoundEnd {
+        winner: winner.clone(),
+    }));
+    scoreboard.round_end = Some(RoundEnd {
+        winner,
+        restart_at: clock.now(&time) + server_settings.round_end_delay as f64,
+    });
+}
+
+/// Gives every new map the time limit of the current rotation map,
+/// also when the host changes the map by hand.
+fn restart_round_timer(
+    mut change_map_event: EventReader<ChangeMapLobbyEvent>,
+    mut round_timer: ResMut<RoundTimer>,
+    map_rotation: Res<MapRotation>,
+) {
+    if change_map_event.read().count() > 0 {
+        round_timer.restart(map_rotation.time_limit());
+    }
+}
+
+/// Runs the time of the round, the time does not run while the map is loading.
+fn tick_round_timer(
+    mut round_timer: ResMut<RoundTimer>,
+    map_loader_state: Res<State<MapLoaderState>>,
+    time: Res<Time>,
+) {
+    if *map_loader_state.get() != MapLoaderState::Yes {
+        return;
+    }
+    if let Some(timer) = round_timer.0.as_mut() {
+        timer.tick(time.delta());
+    }
+}
+
+/// Starts the next round when the round end is over.
+///
+/// The rotation moves on to its next map, a rotation of one map restarts the round in place
+/// by respawning every character.
+#[allow(clippy::too_many_arguments)]
+fn restart_round(
+    mut scoreboard: ResMut<Scoreboard>,
+    mut map_rotation: ResMut<MapRotation>,
+    mut custom_map: ResMut<CustomMap>,
+    mut round_timer: ResMut<RoundTimer>,
+    mut change_map_event: EventWriter<ChangeMapLobbyEvent>,
+    mut round_restart_event: EventWriter<RoundRestartEvent>,
+    mut respawn_query: Query<&mut Respawn, With<Character>>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    let Some(round_end) = scoreboard.round_end.as_ref() else {
+        return;
+    };
+    if clock.now(&time) < round_end.restart_at {
+        return;
+    }
+
+    scoreboard.reset();
+    if map_rotation.has_next() {
+        let current = map_rotation
+            .current()
+            .map(|rotation_map| (rotation_map.map, rotation_map.file.clone()));
+        // the rotation can come back to the same map when it skips maps it can not load
+        if map_rotation.advance(&mut custom_map, &mut change_map_event)
+            && map_rotation
+                .current()
+                .map(|rotation_map| (rotation_map.map, rotation_map.file.clone()))
+                != current
+        {
+            // restarted here as well, the round must not end on the old time before the new
+            // map is announced
+            round_timer.restart(map_rotation.time_limit());
+            return;
+        }
+    }
+
+    round_timer.restart(map_rotation.time_limit());
+    round_restart_event.send(RoundRestartEvent);
+    for mut respawn in respawn_query.iter_mut() {
+        respawn.insert_reason(DespawnReason::Forced);
+    }
+}
+
+/// Starts a new round when the host changes the map.
+fn reset_on_map_change(
+    mut change_map_event: EventReader<ChangeMapLobbyEvent>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    if change_map_event.read().count() > 0 {
+        scoreboard.reset();
+    }
+}
+
+/// Sends the whole scoreboard whenever a score changes.
+fn send_scoreboard(
+    mut server: ResMut<RenetServer>,
+    mut synced_clients: Local<SyncedClients>,
+    scoreboard: Res<Scoreboard>,
+    clients_load_state: Res<ClientsLoadState>,
+) {
+    synced_clients
+        .sync(&clients_load_state)
+        .send(&mut server, scoreboard.is_changed(), || {
+            ServerMessages::Scoreboard {
+                scoreboard: scoreboard.clone(),
+            }
+        });
+}
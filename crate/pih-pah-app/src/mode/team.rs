@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bevy::prelude::*;
+use renet::{DefaultChannel, RenetClient, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::character::Dead;
+use crate::component::{DespawnReason, Respawn};
+use crate::lobby::{ClientMessages, Lobby, Notices, PlayerId, ServerMessages};
+use crate::map::{SpawnPoint, TeamSpawnPoints};
+
+use super::GameMode;
+
+/// Share of the team color in the color of a character, the rest is the color of the player.
+const TEAM_TINT: f32 = 0.7;
+
+/// A side of a team game mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Team::Red => Color::rgb(0.9, 0.2, 0.2),
+            Team::Blue => Color::rgb(0.2, 0.4, 0.9),
+        }
+    }
+
+    pub fn other(&self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+
+    /// Mixes the color of a player with the team color, players of a team look alike
+    /// but can still be told apart.
+    pub fn tint(&self, color: Color) -> Color {
+        let team = self.color().as_rgba_f32();
+        let player = color.as_rgba_f32();
+        let mix = |i: usize| team[i] * TEAM_TINT + player[i] * (1. - TEAM_TINT);
+        Color::rgba(mix(0), mix(1), mix(2), player[3])
+    }
+}
+
+impl FromStr for Team {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "red" => Ok(Team::Red),
+            "blue" => Ok(Team::Blue),
+            _ => Err(format!("Unknown team {}", s)),
+        }
+    }
+}
+
+/// Lets teammates damage each other, set by the host when the lobby is created.
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct FriendlyFire(pub bool);
+
+/// Moves a player to the other team, sent on the host.
+#[derive(Debug, Event)]
+pub struct SwitchTeamEvent(pub PlayerId);
+
+/// Asks to move the local player to the other team, sent by the game menu.
+#[derive(Debug, Event)]
+pub struct RequestTeamSwitchEvent;
+
+/// Returns the team a new player joins in the mode, the one with fewer players.
+pub fn join_team(lobby: &Lobby, game_mode: GameMode) -> Option<Team> {
+    game_mode.has_teams().then(|| lobby.smallest_team())
+}
+
+/// Returns the spawn points of the team if the map has them, otherwise the common ones.
+pub fn team_spawn_point(
+    spawn_point: &SpawnPoint,
+    team_spawn_points: &TeamSpawnPoints,
+    team: Option<Team>,
+) -> SpawnPoint {
+    team.and_then(|team| team_spawn_points.get(team))
+        .unwrap_or_else(|| spawn_point.clone())
+}
+
+/// Switches the team of the host's player.
+pub fn request_team_switch_host(
+    mut request_event: EventReader<RequestTeamSwitchEvent>,
+    mut switch_team_event: EventWriter<SwitchTeamEvent>,
+) {
+    for _ in request_event.read() {
+        switch_team_event.send(SwitchTeamEvent(PlayerId::HostOrSingle));
+    }
+}
+
+/// Asks the server to switch the team of this client's player.
+pub fn request_team_switch_client(
+    mut request_event: EventReader<RequestTeamSwitchEvent>,
+    mut client: ResMut<RenetClient>,
+) {
+    for _ in request_event.read() {
+        let message = bincode::serialize(&ClientMessages::SwitchTeam).unwrap();
+        client.send_message(DefaultChannel::ReliableUnordered, message);
+    }
+}
+
+/// Keeps the teams even, a player of the bigger team is moved when the difference
+/// is more than one player, like after a player has left.
+pub fn balance_teams(lobby: Res<Lobby>, mut switch_team_event: EventWriter<SwitchTeamEvent>) {
+    if !lobby.is_changed() {
+        return;
+    }
+    let [red, blue] = Team::ALL.map(|team| lobby.team_size(team));
+    if red.abs_diff(blue) <= 1 {
+        return;
+    }
+
+    let bigger = if red > blue { Team::Red } else { Team::Blue };
+    if let Some((player_id, _)) = lobby
+        .players
+        .iter()
+        .find(|(_, player_data)| player_data.team == Some(bigger))
+    {
+        switch_team_event.send(SwitchTeamEvent(*player_id));
+    }
+}
+
+/// Moves players to the other team and respawns them at the spawn points of their new team,
+/// dead ones respawn there when due.
+///
+/// A player joins the other team if it has fewer players. When the teams are even the request
+/// waits for a player of the other team to ask as well, then both players swap teams.
+/// The player is told why a switch has not been made.
+#[allow(clippy::too_many_arguments)]
+pub fn switch_teams(
+    mut switch_team_event: EventReader<SwitchTeamEvent>,
+    mut waiting: Local<HashMap<PlayerId, Team>>,
+    mut server: ResMut<RenetServer>,
+    mut lobby: ResMut<Lobby>,
+    mut notices: ResMut<Notices>,
+    mut respawn_query: Query<(&mut Respawn, Has<Dead>)>,
+    spawn_point: Res<SpawnPoint>,
+    team_spawn_points: Res<TeamSpawnPoints>,
+    time: Res<Time>,
+) {
+    for SwitchTeamEvent(player_id) in switch_team_event.read() {
+        let Some(team) = lobby.players.get(player_id).and_then(|player| player.team) else {
+            continue;
+        };
+        let new_team = team.other();
+        // requests of players that have left or switched since are dropped
+        waiting.retain(|waiting_id, waiting_team| {
+            lobby.players.get(waiting_id).and_then(|player| player.team) == Some(*waiting_team)
+        });
+
+        let (team_size, new_team_size) = (lobby.team_size(team), lobby.team_size(new_team));
+        let mut switched = vec![(*player_id, new_team)];
+        if new_team_size == team_size {
+            let partner = waiting
+                .iter()
+                .find(|(_, waiting_team)| **waiting_team == new_team)
+                .map(|(waiting_id, _)| *waiting_id);
+            let Some(partner) = partner else {
+                waiting.insert(*player_id, team);
+                notify(
+                    &mut server,
+                    &mut notices,
+                    &time,
+                    *player_id,
+                    format!(
+                        "Teams are even, you will switch when a player of {} asks to switch",
+                        new_team.name()
+                    ),
+                );
+                continue;
+            };
+            waiting.remove(&partner);
+            switched.push((partner, team));
+        } else if new_team_size > team_size {
+            notify(
+                &mut server,
+                &mut notices,
+                &time,
+                *player_id,
+                format!("Team {} has more players", new_team.name()),
+            );
+            continue;
+        }
+        waiting.remove(player_id);
+
+        for (player_id, new_team) in switched {
+            let Some(player_data) = lobby.players.get_mut(&player_id) else {
+                continue;
+            };
+            player_data.team = Some(new_team);
+
+            if let Ok((mut respawn, dead)) = respawn_query.get_mut(player_data.entity) {
+                respawn.replase_spawn_point(team_spawn_point(
+                    &spawn_point,
+                    &team_spawn_points,
+                    Some(new_team),
+                ));
+                if !dead {
+                    respawn.insert_reason(DespawnReason::Forced);
+                }
+            }
+
+            let message = bincode::serialize(&ServerMessages::PlayerTeam {
+                id: player_id,
+                team: new_team,
+            })
+            .unwrap();
+            server.broadcast_message(DefaultChannel::ReliableOrdered, message);
+        }
+    }
+}
+
+/// Shows a notice to one player, the host's player sees it without a message.
+fn notify(
+    server: &mut RenetServer,
+    notices: &mut Notices,
+    time: &Time,
+    player_id: PlayerId,
+    message: String,
+) {
+    match player_id.client_id() {
+        Some(client_id) => {
+            let message = bincode::serialize(&ServerMessages::Broadcast { message }).unwrap();
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, message);
+        }
+        None => notices.push(message, time.elapsed_seconds()),
+    }
+}
+
+/// Gives characters the spawn points of their team when the map has been scanned for them.
+pub fn apply_team_spawn_points(
+    lobby: Res<Lobby>,
+    spawn_point: Res<SpawnPoint>,
+    team_spawn_points: Res<TeamSpawnPoints>,
+    mut respawn_query: Query<&mut Respawn>,
+) {
+    if !team_spawn_points.is_changed() {
+        return;
+    }
+    for player_data in lobby.players.values() {
+        let Some(team) = player_data.team else {
+            continue;
+        };
+        if let Ok(mut respawn) = respawn_query.get_mut(player_data.entity) {
+            respawn.replase_spawn_point(team_spawn_point(
+                &spawn_point,
+                &team_spawn_points,
+                Some(team),
+            ));
+        }
+    }
+}
+
+/// Gives the characters the colors of their teams when players join or switch teams.
+pub fn tint_characters(
+    lobby: Res<Lobby>,
+    material_query: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !lobby.is_changed() {
+        return;
+    }
+    for player_data in lobby.players.values() {
+        let Ok(handle) = material_query.get(player_data.entity) else {
+            continue;
+        };
+        let color = player_data.character_color();
+        if materials
+            .get(handle)
+            .is_some_and(|material| material.base_color != color)
+        {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color = color;
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 #![allow(clippy::module_inception)]
 
+mod server_settings;
 mod settings;
+
+pub use server_settings::*;
 pub use settings::*;
@@ -0,0 +1,163 @@
+use std::{env, fs::File};
+
+use bevy::ecs::system::{Commands, Resource};
+use serde::{self, Deserialize, Serialize};
+
+use crate::lobby::compression::Codec;
+use crate::map::MapState;
+use crate::mode::GameMode;
+
+/// One map of the [`ServerSettings::map_rotation`].
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RotationMap {
+    pub map: MapState,
+    /// File name of a custom map in [`CUSTOM_MAP_FOLDER`](crate::map::CUSTOM_MAP_FOLDER),
+    /// used with [`MapState::Custom`].
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Seconds the map is played before the next one, `None` to play it until the host changes it.
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+}
+
+/// Settings of the hosted lobby, read from `server.yaml` next to the executable.
+#[derive(Deserialize, Serialize, Debug, Clone, Resource)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub bind_address: String,
+    pub port: u16,
+    pub max_clients: usize,
+    pub lobby_name: String,
+    /// Clients must know it to connect, `None` for an open lobby.
+    pub password: Option<String>,
+    /// Snapshots sent to clients per second.
+    pub tick_rate: u32,
+    /// Distance from a client's character within which players and actors are sent to the client.
+    pub relevance_radius: f32,
+    /// Bytes per second of snapshots sent to each client, one snapshot never exceeds
+    /// [`MAX_SNAPSHOT_BYTES`](crate::lobby::priority::MAX_SNAPSHOT_BYTES).
+    pub snapshot_bandwidth: u32,
+    /// Compression of large reliable messages, like the world state sent to joining clients.
+    pub reliable_compression: Codec,
+    /// Compression of map files sent to clients.
+    pub map_transfer_compression: Codec,
+    /// Compression of snapshots, [`Codec::Lz4Dictionary`] suits their small size.
+    pub snapshot_compression: Codec,
+    /// Seconds a killed character waits for its respawn.
+    pub respawn_delay: f32,
+    /// Mode selected in the menu when a lobby is created.
+    pub game_mode: GameMode,
+    /// Lets teammates damage each other, selected in the menu when a lobby is created.
+    pub friendly_fire: bool,
+    /// Score that wins a round of deathmatch, `0` to end rounds only by the time limit of the map.
+    pub score_limit: u32,
+    /// Captures that win a round of capture the flag, `0` to end rounds only by the time limit.
+    pub capture_limit: u32,
+    /// Points that win a round of king of the hill, a team alone in a control zone gets
+    /// a point every second, `0` to end rounds only by the time limit.
+    pub hill_limit: u32,
+    /// Seconds the result of a round is shown before the next round starts.
+    pub round_end_delay: f32,
+    /// Maps the lobby plays one after another, the first one is loaded when the lobby is created.
+    pub map_rotation: Vec<RotationMap>,
+    /// Local port of the remote admin console, see [`Rcon`](crate::lobby::rcon::Rcon).
+    pub rcon_port: u16,
+    /// Password of the remote admin console, the console is disabled without it.
+    pub rcon_password: Option<String>,
+    /// Local port of the metrics endpoint, see [`Metrics`](crate::lobby::metrics::Metrics),
+    /// `None` to disable it.
+    pub metrics_port: Option<u16>,
+    /// Writes the events of every match into a file, see [`MatchLog`](crate::lobby::match_log::MatchLog).
+    pub match_history: bool,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0".to_string(),
+            port: 5000,
+            max_clients: 64,
+            lobby_name: "pih-pah".to_string(),
+            password: None,
+            tick_rate: 60,
+            relevance_radius: 200.,
+            snapshot_bandwidth: 64_000,
+            reliable_compression: Codec::Lz4,
+            map_transfer_compression: Codec::Lz4,
+            snapshot_compression: Codec::Lz4Dictionary,
+            respawn_delay: 3.,
+            game_mode: GameMode::Deathmatch,
+            friendly_fire: false,
+            score_limit: 20,
+            capture_limit: 3,
+            hill_limit: 100,
+            round_end_delay: 10.,
+            map_rotation: vec![
+                RotationMap {
+                    map: MapState::ShootingRange,
+                    file: None,
+                    time_limit: Some(600.),
+                },
+                RotationMap {
+                    map: MapState::GravityHell,
+                    file: None,
+                    time_limit: Some(600.),
+                },
+            ],
+            rcon_port: 5001,
+            rcon_password: None,
+            metrics_port: None,
+            match_history: true,
+        }
+    }
+}
+
+/// Reads `server.yaml` (or `server.yml`) and creates it with defaults if there is none.
+pub fn load_server_settings(mut commands: Commands) {
+    let exe_path = env::current_exe().expect("Failed to find executable path");
+
+    let exe_dir = exe_path
+        .parent()
+        .expect("Failed to find executable directory");
+
+    let yaml_path = exe_dir.join("server.yaml");
+    let yml_path = exe_dir.join("server.yml");
+
+    let server_settings = if let Some(path) = [&yaml_path, &yml_path]
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        let file = File::open(path).unwrap_or_else(|err| {
+            panic!(
+                "Failed to open exist server settings file ({:#?}) \n error: {:#?}",
+                path, err
+            )
+        });
+
+        serde_yaml::from_reader(file).unwrap_or_else(|err| {
+            panic!(
+                "Failed to read server settings file ({:#?}) \n error: {:#?}",
+                path, err
+            )
+        })
+    } else {
+        let mut file = File::create(&yaml_path).unwrap_or_else(|err| {
+            panic!(
+                "Failed to create server settings file ({:#?}) \n error: {:#?}",
+                &yaml_path, err
+            )
+        });
+
+        let server_settings = ServerSettings::default();
+        serde_yaml::to_writer(&mut file, &server_settings).unwrap_or_else(|err| {
+            panic!(
+                "Failed to write to server settings file ({:#?}) \n error: {:#?}",
+                &yaml_path, err
+            )
+        });
+
+        server_settings
+    };
+
+    commands.insert_resource(server_settings);
+}
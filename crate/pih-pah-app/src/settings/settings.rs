@@ -21,6 +21,8 @@ use serde::{self, Deserialize, Serialize};
 
 use crate::sound::MenuMusic;
 
+use super::load_server_settings;
+
 #[allow(dead_code)]
 #[derive(Debug, Resource, Default)]
 struct AppliedSettings {
@@ -55,7 +57,7 @@ impl Plugin for SettingsPlugins {
         app.init_resource::<AppliedSettings>()
             .add_event::<ApplySettings>()
             .add_event::<ExemptSettings>()
-            .add_systems(PostStartup, setup)
+            .add_systems(PostStartup, (setup, load_server_settings))
             .add_systems(Last, (apply_settings, exempt_settings));
     }
 }
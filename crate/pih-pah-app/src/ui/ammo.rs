@@ -0,0 +1,84 @@
+use crate::character::{WeaponState, Weapons};
+use crate::lobby::clock::GameClock;
+use crate::lobby::LobbyState;
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use crate::world::Me;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::UiState;
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+pub struct AmmoPlugins;
+
+impl Plugin for AmmoPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            ammo.run_if(
+                in_state(UiState::GameMenu)
+                    .and_then(not(in_state(LobbyState::None)))
+                    .and_then(resource_exists::<Weapons>())
+                    .and_then(resource_exists::<GameClock>()),
+            ),
+        );
+    }
+}
+
+/// Shows the selected weapon of the player and its ammo.
+fn ammo(
+    mut context: EguiContexts,
+    query: Query<&WeaponState, With<Me>>,
+    weapons: Res<Weapons>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    let Ok(state) = query.get_single() else {
+        return;
+    };
+    let Some(weapon) = weapons.get(state.selected) else {
+        return;
+    };
+    let text = match state.reload_end {
+        Some(reload_end) => format!(
+            "[{}] {} reloading {:.1}s",
+            state.selected + 1,
+            weapon.name,
+            clock.until(&time, reload_end)
+        ),
+        None => format!(
+            "[{}] {} {}/{}",
+            state.selected + 1,
+            weapon.name,
+            state.ammo(),
+            weapon.magazine
+        ),
+    };
+    let text = match state.charge(&weapons, clock.now(&time)) {
+        Some(charge) => format!("{} charge {:.0}%", text, charge * 100.),
+        None => text,
+    };
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Ammo".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            ui.label(rich_text(text, Module(&MODULE), &font));
+        });
+}
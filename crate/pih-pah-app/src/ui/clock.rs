@@ -0,0 +1,63 @@
+use crate::lobby::clock::GameClock;
+use crate::lobby::LobbyState;
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{UiState, ViewportRect};
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+pub struct ClockPlugins;
+
+impl Plugin for ClockPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            match_clock.run_if(
+                in_state(UiState::GameMenu)
+                    .and_then(not(in_state(LobbyState::None)))
+                    .and_then(resource_exists::<GameClock>()),
+            ),
+        );
+    }
+}
+
+/// Shows the time left in the current round, counted down from the server timestamp.
+fn match_clock(
+    mut context: EguiContexts,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+    ui_frame_rect: Res<ViewportRect>,
+) {
+    let Some(match_end) = clock.match_end else {
+        return;
+    };
+    let time_left = clock.until(&time, match_end) as u64;
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Clock".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::RIGHT_TOP, [-10., ui_frame_rect.min.y + 10.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            ui.label(rich_text(
+                format!("Round ends in {}:{:02}", time_left / 60, time_left % 60),
+                Module(&MODULE),
+                &font,
+            ));
+        });
+}
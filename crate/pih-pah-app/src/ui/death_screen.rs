@@ -0,0 +1,93 @@
+use crate::character::Dead;
+use crate::lobby::clock::GameClock;
+use crate::lobby::{Lobby, LobbyState};
+use crate::mode::Scoreboard;
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use crate::world::Me;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::UiState;
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+pub struct DeathScreenPlugins;
+
+impl Plugin for DeathScreenPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            death_screen.run_if(
+                in_state(UiState::GameMenu)
+                    .and_then(not(in_state(LobbyState::None)))
+                    .and_then(resource_exists::<GameClock>()),
+            ),
+        );
+    }
+}
+
+/// Shows who has killed the player and counts down to the respawn.
+fn death_screen(
+    mut context: EguiContexts,
+    query: Query<(Entity, &Dead), With<Me>>,
+    lobby: Option<Res<Lobby>>,
+    scoreboard: Option<Res<Scoreboard>>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    // the round end screen is shown instead
+    if scoreboard.is_some_and(|scoreboard| scoreboard.is_over()) {
+        return;
+    }
+    let Ok((entity, dead)) = query.get_single() else {
+        return;
+    };
+    let respawn_in = clock.until(&time, dead.respawn_at).ceil() as u64;
+
+    let message = match (dead.killer, lobby) {
+        (Some(killer), Some(lobby)) => {
+            let own_id = lobby
+                .players
+                .iter()
+                .find(|(_, player_data)| player_data.entity == entity)
+                .map(|(player_id, _)| *player_id);
+            if Some(killer) == own_id {
+                "You killed yourself".to_string()
+            } else {
+                format!("Killed by {}", lobby.username(killer))
+            }
+        }
+        // a single game has no lobby and no other players
+        (Some(_), None) => "You killed yourself".to_string(),
+        (None, _) => "You died".to_string(),
+    };
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        size: 24.,
+    };
+
+    egui::Window::new(rich_text("Death".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(rich_text(message, Module(&MODULE), &font));
+                ui.label(rich_text(
+                    format!("Respawn in {}", respawn_in),
+                    Module(&MODULE),
+                    &font,
+                ));
+            });
+        });
+}
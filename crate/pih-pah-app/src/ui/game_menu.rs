@@ -1,11 +1,13 @@
 use crate::lobby::{ChangeMapLobbyEvent, LobbyState};
-use crate::map::MapState;
+use crate::map::{custom_maps, open_custom_map, CustomMap, MapState};
+use crate::mode::{RequestTeamSwitchEvent, Scoreboard};
 use crate::settings::{ApplySettings, ExemptSettings, Settings};
 use crate::ui::{rich_text, TRANSPARENT};
 use crate::util::i18n::Uniq::Module;
 use bevy::prelude::*;
 use bevy_egui::egui::Align2;
 use bevy_egui::{egui, EguiContexts};
+use std::fmt::Display;
 
 use super::{MouseGrabState, UiState, ViewportRect};
 
@@ -13,19 +15,38 @@ lazy_static::lazy_static! {
     static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
 }
 
+/// A map that can be selected in the game menu.
+#[derive(Debug, Clone, PartialEq)]
+enum MapChoice {
+    Map(MapState),
+    /// File name of a custom map.
+    Custom(String),
+}
+
+impl Display for MapChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapChoice::Map(map_state) => write!(f, "{}", map_state),
+            MapChoice::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
 #[derive(Resource)]
 struct EguiState {
     is_active: bool,
-    selected_map: MapState,
-    selected_map_applied: MapState,
+    selected_map: MapChoice,
+    selected_map_applied: MapChoice,
+    custom_maps: Vec<String>,
 }
 
 impl Default for EguiState {
     fn default() -> Self {
         Self {
             is_active: false,
-            selected_map: MapState::ShootingRange,
-            selected_map_applied: MapState::ShootingRange,
+            selected_map: MapChoice::Map(MapState::ShootingRange),
+            selected_map_applied: MapChoice::Map(MapState::ShootingRange),
+            custom_maps: Vec::new(),
         }
     }
 }
@@ -75,6 +96,7 @@ impl Plugin for GameMenuPlugins {
                         .and_then(in_state(WindowState::Settings)),
                 ),
             )
+            .add_systems(OnEnter(WindowState::Settings), refresh_custom_maps)
             .add_systems(OnExit(WindowState::Settings), exempt_setting);
     }
 }
@@ -91,6 +113,8 @@ fn menu(
     ui_frame_rect: ResMut<ViewportRect>,
     mut windows: Query<&Window>,
     mut nex_state_mouse_grab: ResMut<NextState<MouseGrabState>>,
+    mut request_team_switch: EventWriter<RequestTeamSwitchEvent>,
+    scoreboard: Option<Res<Scoreboard>>,
 ) {
     let ctx = context.ctx_mut();
 
@@ -129,6 +153,15 @@ fn menu(
             {
                 next_state_menu_window.set(WindowState::Settings);
             }
+            if scoreboard
+                .as_ref()
+                .is_some_and(|scoreboard| scoreboard.mode.has_teams())
+                && ui
+                    .button(rich_text("Switch team".to_string(), Module(&MODULE), &font))
+                    .clicked()
+            {
+                request_team_switch.send(RequestTeamSwitchEvent);
+            }
             if ui
                 .button(rich_text("Menu".to_string(), Module(&MODULE), &font))
                 .clicked()
@@ -153,6 +186,7 @@ fn settings_window(
     ui_frame_rect: ResMut<ViewportRect>,
     mut settings_applying: EventWriter<ApplySettings>,
     mut change_map: EventWriter<ChangeMapLobbyEvent>,
+    mut custom_map: ResMut<CustomMap>,
 ) {
     let frame_size = ui_frame_rect.max - ui_frame_rect.min;
 
@@ -196,14 +230,21 @@ fn settings_window(
                     .show_ui(ui, |ui| {
                         ui.selectable_value(
                             &mut state.selected_map,
-                            MapState::ShootingRange,
+                            MapChoice::Map(MapState::ShootingRange),
                             MapState::ShootingRange.to_string(),
                         );
                         ui.selectable_value(
                             &mut state.selected_map,
-                            MapState::GravityHell,
+                            MapChoice::Map(MapState::GravityHell),
                             MapState::GravityHell.to_string(),
                         );
+                        for name in state.custom_maps.clone() {
+                            ui.selectable_value(
+                                &mut state.selected_map,
+                                MapChoice::Custom(name.clone()),
+                                name,
+                            );
+                        }
                     });
                 });
             }
@@ -218,22 +259,14 @@ fn settings_window(
                     .button(rich_text("Apply".to_string(), Module(&MODULE), &font))
                     .clicked()
                 {
-                    if state.selected_map_applied != state.selected_map {
-                        state.selected_map_applied = state.selected_map;
-                        // next_state_map.set(state.selected_map);
-                        change_map.send(ChangeMapLobbyEvent(state.selected_map));
-                    }
+                    apply_map(&mut state, &mut custom_map, &mut change_map);
                     settings_applying.send(ApplySettings);
                 }
                 if ui
                     .button(rich_text("Ok".to_string(), Module(&MODULE), &font))
                     .clicked()
                 {
-                    if state.selected_map_applied != state.selected_map {
-                        state.selected_map_applied = state.selected_map;
-                        // next_state_map.set(state.selected_map);
-                        change_map.send(ChangeMapLobbyEvent(state.selected_map));
-                    }
+                    apply_map(&mut state, &mut custom_map, &mut change_map);
                     settings_applying.send(ApplySettings);
                     next_state_menu_window.set(WindowState::None);
                 }
@@ -241,7 +274,37 @@ fn settings_window(
         });
 }
 
+/// Changes the map if another one has been selected.
+fn apply_map(
+    state: &mut EguiState,
+    custom_map: &mut CustomMap,
+    change_map: &mut EventWriter<ChangeMapLobbyEvent>,
+) {
+    if state.selected_map_applied == state.selected_map {
+        return;
+    }
+
+    match state.selected_map.clone() {
+        MapChoice::Map(map_state) => change_map.send(ChangeMapLobbyEvent(map_state)),
+        MapChoice::Custom(name) => match open_custom_map(&name) {
+            Ok(local) => {
+                custom_map.0 = Some(local);
+                change_map.send(ChangeMapLobbyEvent(MapState::Custom));
+            }
+            Err(err) => {
+                error!("Failed to open map {}: {}", name, err);
+                return;
+            }
+        },
+    }
+    state.selected_map_applied = state.selected_map.clone();
+}
+
+fn refresh_custom_maps(mut state: ResMut<EguiState>) {
+    state.custom_maps = custom_maps();
+}
+
 fn exempt_setting(mut event: EventWriter<ExemptSettings>, mut state: ResMut<EguiState>) {
-    state.selected_map = state.selected_map_applied;
+    state.selected_map = state.selected_map_applied.clone();
     event.send(ExemptSettings);
 }
@@ -0,0 +1,102 @@
+use crate::character::Health;
+use crate::lobby::LobbyState;
+use crate::ui::{rich_text, MainCamera, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use crate::world::Me;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::UiState;
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+/// Height of a health bar above the center of its character.
+const HEALTH_BAR_OFFSET: f32 = 2.;
+const HEALTH_BAR_SIZE: egui::Vec2 = egui::vec2(40., 4.);
+
+pub struct HealthPlugins;
+
+impl Plugin for HealthPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (health_bars, own_health)
+                .run_if(in_state(UiState::GameMenu).and_then(not(in_state(LobbyState::None)))),
+        );
+    }
+}
+
+/// Returns the color of a health bar, from red when empty to green when full.
+fn health_color(fraction: f32) -> egui::Color32 {
+    egui::Color32::from_rgb(((1. - fraction) * 255.) as u8, (fraction * 255.) as u8, 0)
+}
+
+/// Draws health bars above the visible characters of the other players.
+fn health_bars(
+    mut context: EguiContexts,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    character_query: Query<(&Health, &GlobalTransform, &Visibility), Without<Me>>,
+) {
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+
+    let painter = context.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("health_bars"),
+    ));
+
+    for (health, transform, visibility) in character_query.iter() {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        let Some(position) = camera.world_to_viewport(
+            camera_transform,
+            transform.translation() + Vec3::Y * HEALTH_BAR_OFFSET,
+        ) else {
+            continue;
+        };
+
+        let rect =
+            egui::Rect::from_center_size(egui::pos2(position.x, position.y), HEALTH_BAR_SIZE);
+        let mut filled = rect;
+        filled.set_width(rect.width() * health.fraction());
+        painter.rect_filled(rect, 0., egui::Color32::from_black_alpha(160));
+        painter.rect_filled(filled, 0., health_color(health.fraction()));
+    }
+}
+
+/// Shows the health of the player's character.
+fn own_health(mut context: EguiContexts, query: Query<&Health, With<Me>>) {
+    let Ok(health) = query.get_single() else {
+        return;
+    };
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Health".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::CENTER_BOTTOM, [0., -10.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            ui.label(
+                rich_text(
+                    format!("Health {:.0}/{:.0}", health.current, health.max),
+                    Module(&MODULE),
+                    &font,
+                )
+                .color(health_color(health.fraction())),
+            );
+        });
+}
@@ -0,0 +1,76 @@
+use crate::lobby::client::MapDownloadError;
+use crate::lobby::{LoadingPlayers, LobbyState, MapLoaderState};
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{UiState, ViewportRect};
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+pub struct LoadingPlugins;
+
+impl Plugin for LoadingPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            loading_indicator
+                .run_if(in_state(UiState::GameMenu).and_then(not(in_state(LobbyState::None)))),
+        );
+    }
+}
+
+/// Shows the map loading progress of this player and of the players everyone waits for.
+fn loading_indicator(
+    mut context: EguiContexts,
+    map_loader_state: Res<State<MapLoaderState>>,
+    loading_players: Res<LoadingPlayers>,
+    map_download_error: Option<Res<MapDownloadError>>,
+    ui_frame_rect: Res<ViewportRect>,
+) {
+    let is_loading = *map_loader_state.get() == MapLoaderState::No;
+    if !is_loading && loading_players.0.is_empty() {
+        return;
+    }
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Loading".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::CENTER_TOP, [0., ui_frame_rect.min.y + 10.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            if let Some(error) = map_download_error {
+                ui.label(rich_text(
+                    format!("{}, disconnected", error.0),
+                    Module(&MODULE),
+                    &font,
+                ));
+            } else if is_loading {
+                ui.label(rich_text(
+                    "Loading map...".to_string(),
+                    Module(&MODULE),
+                    &font,
+                ));
+            }
+            if !loading_players.0.is_empty() {
+                ui.label(rich_text(
+                    format!("Waiting for: {}", loading_players.0.join(", ")),
+                    Module(&MODULE),
+                    &font,
+                ));
+            }
+        });
+}
@@ -1,5 +1,8 @@
 use crate::lobby::{ClientResource, HostResource, LobbyState};
-use crate::settings::{ApplySettings, ExemptSettings, Settings};
+use crate::mode::GameMode;
+use crate::settings::{
+    load_server_settings, ApplySettings, ExemptSettings, ServerSettings, Settings,
+};
 use crate::ui::{rich_text, TRANSPARENT};
 use crate::util::i18n::Uniq::Module;
 use bevy::app::AppExit;
@@ -23,7 +26,10 @@ enum MultiplayerState {
 struct State {
     multiplayer_state: MultiplayerState,
     host_port: String,
+    game_mode: GameMode,
+    friendly_fire: bool,
     join_address: String,
+    join_password: String,
     username: String,
 }
 
@@ -40,7 +46,10 @@ impl Default for State {
         Self {
             multiplayer_state: MultiplayerState::Create,
             host_port: "5000".to_string(),
+            game_mode: GameMode::Deathmatch,
+            friendly_fire: false,
             join_address: "127.0.0.1:5000".to_string(),
+            join_password: String::new(),
             username: "noname".to_string(),
         }
     }
@@ -52,6 +61,10 @@ impl Plugin for MenuPlugins {
     fn build(&self, app: &mut App) {
         app.init_resource::<State>()
             .add_state::<WindowState>()
+            .add_systems(
+                PostStartup,
+                apply_server_settings.after(load_server_settings),
+            )
             .add_systems(Update, menu.run_if(in_state(UiState::Menu)))
             .add_systems(
                 Update,
@@ -135,6 +148,13 @@ fn menu(
         });
 }
 
+/// Uses the port, the game mode and the friendly fire from [`ServerSettings`] for new lobbies.
+fn apply_server_settings(mut state: ResMut<State>, server_settings: Res<ServerSettings>) {
+    state.host_port = server_settings.port.to_string();
+    state.game_mode = server_settings.game_mode;
+    state.friendly_fire = server_settings.friendly_fire;
+}
+
 #[allow(clippy::too_many_arguments)]
 fn multiplayer_window(
     mut next_state_ui: ResMut<NextState<UiState>>,
@@ -147,6 +167,7 @@ fn multiplayer_window(
     ui_frame_rect: ResMut<ViewportRect>,
     mut client_resource: ResMut<ClientResource>,
     mut nex_state_mouse_grab: ResMut<NextState<MouseGrabState>>,
+    server_settings: Res<ServerSettings>,
 ) {
     // let window = windows.single_mut();
     // let window_size = egui::vec2(window.width(), window.height());
@@ -192,14 +213,28 @@ fn multiplayer_window(
                         ui.label("Username:");
                         ui.text_edit_singleline(&mut state.username);
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Mode:");
+                        for game_mode in GameMode::ALL {
+                            ui.radio_value(&mut state.game_mode, game_mode, game_mode.name());
+                        }
+                    });
+                    if state.game_mode.has_teams() {
+                        ui.checkbox(&mut state.friendly_fire, "Friendly fire");
+                    }
                     if ui
                         .button(rich_text("Create".to_string(), Module(&MODULE), &font))
                         .clicked()
                     {
                         nex_state_mouse_grab.set(MouseGrabState::Enable);
-                        host_resource.address =
-                            Some(format!("0.0.0.0:{}", state.host_port.clone()));
+                        host_resource.address = Some(format!(
+                            "{}:{}",
+                            server_settings.bind_address,
+                            state.host_port.clone()
+                        ));
                         host_resource.username = Some(state.username.clone());
+                        host_resource.game_mode = state.game_mode;
+                        host_resource.friendly_fire = state.friendly_fire;
                         next_state_menu_window.set(WindowState::None);
                         next_state_ui.set(UiState::GameMenu);
 
@@ -220,6 +255,10 @@ fn multiplayer_window(
                         ui.label("Address:");
                         ui.text_edit_singleline(&mut state.join_address);
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Password:");
+                        ui.add(egui::TextEdit::singleline(&mut state.join_password).password(true));
+                    });
                     ui.horizontal(|ui| {
                         ui.label("Username:");
                         ui.text_edit_singleline(&mut state.username);
@@ -231,6 +270,8 @@ fn multiplayer_window(
                         nex_state_mouse_grab.set(MouseGrabState::Enable);
                         client_resource.address = Some(state.join_address.clone());
                         client_resource.username = Some(state.username.clone());
+                        client_resource.password = Some(state.join_password.clone())
+                            .filter(|password| !password.is_empty());
                         next_state_menu_window.set(WindowState::None);
                         state.multiplayer_state = MultiplayerState::Create;
                         next_state_ui.set(UiState::GameMenu);
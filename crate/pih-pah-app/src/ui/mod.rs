@@ -1,13 +1,29 @@
 #![allow(clippy::module_inception)]
 
+mod ammo;
+mod clock;
+mod death_screen;
 mod debug;
 mod egui_frame_preset;
 mod game_menu;
+mod health;
+mod loading;
 mod menu;
+mod notice;
+mod scoreboard;
+mod trajectory;
 mod ui;
 
+pub use ammo::*;
+pub use clock::*;
+pub use death_screen::*;
 pub use debug::*;
 use egui_frame_preset::*;
 pub use game_menu::*;
+pub use health::*;
+pub use loading::*;
 pub use menu::*;
+pub use notice::*;
+pub use scoreboard::*;
+pub use trajectory::*;
 pub use ui::*;
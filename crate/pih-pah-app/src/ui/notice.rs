@@ -0,0 +1,56 @@
+use crate::lobby::{LobbyState, Notices};
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{UiState, ViewportRect};
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+pub struct NoticePlugins;
+
+impl Plugin for NoticePlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            notices.run_if(in_state(UiState::GameMenu).and_then(not(in_state(LobbyState::None)))),
+        );
+    }
+}
+
+/// Shows the recent messages broadcast by the server.
+fn notices(
+    mut context: EguiContexts,
+    notices: Res<Notices>,
+    time: Res<Time>,
+    ui_frame_rect: Res<ViewportRect>,
+) {
+    let now = time.elapsed_seconds();
+    if notices.actual(now).next().is_none() {
+        return;
+    }
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Notices".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::LEFT_BOTTOM, [ui_frame_rect.min.x + 10., -10.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            for notice in notices.actual(now) {
+                ui.label(egui::RichText::new(&notice.message).font(font.clone()));
+            }
+        });
+}
@@ -0,0 +1,203 @@
+use crate::lobby::clock::GameClock;
+use crate::lobby::Lobby;
+use crate::lobby::LobbyState;
+use crate::mode::{ControlZone, Flag, FlagState, GameMode, Scoreboard, Team, ZoneState};
+use crate::ui::{rich_text, TRANSPARENT};
+use crate::util::i18n::Uniq::Module;
+use bevy::prelude::*;
+use bevy_egui::egui::Align2;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{UiState, ViewportRect};
+
+lazy_static::lazy_static! {
+    static ref MODULE: &'static str = module_path!().splitn(3, ':').nth(2).unwrap_or(module_path!());
+}
+
+/// Players listed by the score HUD.
+const HUD_PLAYERS: usize = 5;
+
+pub struct ScoreboardPlugins;
+
+impl Plugin for ScoreboardPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                score_hud,
+                round_end_screen.run_if(resource_exists::<GameClock>()),
+            )
+                .run_if(
+                    in_state(UiState::GameMenu)
+                        .and_then(not(in_state(LobbyState::None)))
+                        .and_then(resource_exists::<Scoreboard>()),
+                ),
+        );
+    }
+}
+
+fn team_color(team: Team) -> egui::Color32 {
+    let [r, g, b, _] = team.color().as_rgba_u8();
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// Shows the mode, the team scores or the best players under the clock,
+/// where the flags are in capture the flag and who holds the zones in king of the hill.
+fn score_hud(
+    mut context: EguiContexts,
+    scoreboard: Res<Scoreboard>,
+    flag_query: Query<&Flag>,
+    zone_query: Query<&ControlZone>,
+    lobby: Option<Res<Lobby>>,
+    ui_frame_rect: Res<ViewportRect>,
+) {
+    if scoreboard.is_over() {
+        return;
+    }
+
+    let ctx = context.ctx_mut();
+
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Score".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::RIGHT_TOP, [-10., ui_frame_rect.min.y + 40.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            let title = match scoreboard.score_limit {
+                0 => scoreboard.mode.name().to_string(),
+                limit => format!("{} to {}", scoreboard.mode.name(), limit),
+            };
+            ui.label(rich_text(title, Module(&MODULE), &font));
+
+            if scoreboard.mode.has_teams() {
+                for team in Team::ALL {
+                    let score = scoreboard.teams.get(&team).copied().unwrap_or_default();
+                    ui.label(
+                        rich_text(format!("{} {}", team.name(), score), Module(&MODULE), &font)
+                            .color(team_color(team)),
+                    );
+                }
+                let flags = match scoreboard.mode {
+                    GameMode::CaptureTheFlag => flag_query.iter().collect(),
+                    _ => Vec::new(),
+                };
+                for flag in flags {
+                    let state = match flag.state {
+                        FlagState::Home => "home".to_string(),
+                        FlagState::Carried(player_id) => format!(
+                            "carried by {}",
+                            lobby
+                                .as_ref()
+                                .map_or("unknown", |lobby| lobby.username(player_id))
+                        ),
+                        FlagState::Dropped { .. } => "dropped".to_string(),
+                    };
+                    ui.label(
+                        rich_text(
+                            format!("{} flag {}", flag.team.name(), state),
+                            Module(&MODULE),
+                            &font,
+                        )
+                        .color(team_color(flag.team)),
+                    );
+                }
+                let zones = match scoreboard.mode {
+                    GameMode::KingOfTheHill => zone_query.iter().collect(),
+                    _ => Vec::new(),
+                };
+                for zone in zones {
+                    let (state, color) = match zone.state {
+                        ZoneState::Neutral => ("free".to_string(), egui::Color32::WHITE),
+                        ZoneState::Held(team) => {
+                            (format!("held by {}", team.name()), team_color(team))
+                        }
+                        ZoneState::Contested => ("contested".to_string(), egui::Color32::YELLOW),
+                    };
+                    ui.label(
+                        rich_text(format!("{} {}", zone.name, state), Module(&MODULE), &font)
+                            .color(color),
+                    );
+                }
+            } else {
+                for (_, player) in scoreboard.ranking().into_iter().take(HUD_PLAYERS) {
+                    ui.label(rich_text(
+                        format!("{} {}", player.username, player.score),
+                        Module(&MODULE),
+                        &font,
+                    ));
+                }
+            }
+        });
+}
+
+/// Shows the winner and the scores of every player until the next round starts.
+fn round_end_screen(
+    mut context: EguiContexts,
+    scoreboard: Res<Scoreboard>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    let Some(round_end) = scoreboard.round_end.as_ref() else {
+        return;
+    };
+    let restart_in = clock.until(&time, round_end.restart_at).ceil() as u64;
+
+    let ctx = context.ctx_mut();
+
+    let title_font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        size: 24.,
+    };
+    let font = egui::FontId {
+        family: egui::FontFamily::Monospace,
+        ..default()
+    };
+
+    egui::Window::new(rich_text("Round end".to_string(), Module(&MODULE), &font))
+        .frame(*TRANSPARENT)
+        .title_bar(false)
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .movable(false)
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                let result = match round_end.winner.as_ref() {
+                    Some(winner) => format!("{} wins", winner),
+                    None => "Draw".to_string(),
+                };
+                ui.label(rich_text(result, Module(&MODULE), &title_font));
+                ui.label(rich_text(
+                    format!("Next round in {}", restart_in),
+                    Module(&MODULE),
+                    &font,
+                ));
+            });
+
+            egui::Grid::new("scoreboard").striped(true).show(ui, |ui| {
+                for header in ["Player", "Kills", "Deaths", "Score"] {
+                    ui.label(rich_text(header.to_string(), Module(&MODULE), &font));
+                }
+                ui.end_row();
+
+                for (_, player) in scoreboard.ranking() {
+                    let mut username = rich_text(player.username.clone(), Module(&MODULE), &font);
+                    if let Some(team) = player.team {
+                        username = username.color(team_color(team));
+                    }
+                    ui.label(username);
+                    ui.label(player.kills.to_string());
+                    ui.label(player.deaths.to_string());
+                    ui.label(player.score.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+}
@@ -0,0 +1,83 @@
+use crate::character::{muzzle, Dead, WeaponState, Weapons};
+use crate::lobby::clock::GameClock;
+use crate::lobby::{LobbyState, PlayerView};
+use crate::world::Me;
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+
+use super::UiState;
+
+/// Seconds of flight shown by the trajectory preview.
+const PREVIEW_TIME: f32 = 3.;
+/// Segments of the trajectory preview.
+const PREVIEW_STEPS: usize = 60;
+
+pub struct TrajectoryPlugins;
+
+impl Plugin for TrajectoryPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            trajectory_preview.run_if(
+                in_state(UiState::GameMenu)
+                    .and_then(not(in_state(LobbyState::None)))
+                    .and_then(resource_exists::<Weapons>())
+                    .and_then(resource_exists::<GameClock>()),
+            ),
+        );
+    }
+}
+
+/// Draws the ballistic arc of the projectile the player is charging, up to the first obstacle.
+#[allow(clippy::type_complexity)]
+fn trajectory_preview(
+    mut gizmos: Gizmos,
+    query: Query<(Entity, &Transform, &PlayerView, &WeaponState), (With<Me>, Without<Dead>)>,
+    spatial_query: SpatialQuery,
+    weapons: Res<Weapons>,
+    gravity: Res<Gravity>,
+    clock: Res<GameClock>,
+    time: Res<Time>,
+) {
+    let Ok((entity, transform, view, state)) = query.get_single() else {
+        return;
+    };
+    let Some(charge) = state.charge(&weapons, clock.now(&time)) else {
+        return;
+    };
+    let Some(weapon) = weapons.get(state.selected) else {
+        return;
+    };
+    if weapon.hitscan.is_some() {
+        return;
+    }
+
+    // projectiles fall with the world gravity scaled by their gravity direction
+    let acceleration = Vec3::NEG_Y * weapon.gravity_scale * gravity.0.length();
+    let velocity = view.direction * Vec3::NEG_Z * weapon.power(charge);
+    let start = muzzle(transform);
+    let filter = SpatialQueryFilter::default().without_entities([entity]);
+    let step = PREVIEW_TIME / PREVIEW_STEPS as f32;
+
+    let mut points = vec![start];
+    for i in 1..=PREVIEW_STEPS {
+        let t = i as f32 * step;
+        let point = start + velocity * t + 0.5 * acceleration * t * t;
+        let previous = points[points.len() - 1];
+        let segment = point - previous;
+        let hit = spatial_query.cast_ray(
+            previous,
+            segment.normalize_or_zero(),
+            segment.length(),
+            true,
+            filter.clone(),
+        );
+        if let Some(hit) = hit {
+            points.push(previous + segment.normalize_or_zero() * hit.time_of_impact);
+            break;
+        }
+        points.push(point);
+    }
+
+    gizmos.linestrip(points, Color::rgba(1., 1., 1., 0.3 + 0.7 * charge));
+}
@@ -1,5 +1,8 @@
 use crate::ui::menu::MenuPlugins;
-use crate::ui::GameMenuPlugins;
+use crate::ui::{
+    AmmoPlugins, ClockPlugins, DeathScreenPlugins, GameMenuPlugins, HealthPlugins, LoadingPlugins,
+    NoticePlugins, ScoreboardPlugins, TrajectoryPlugins,
+};
 use crate::util::i18n::{trans, Uniq};
 use bevy::prelude::*;
 use bevy::window::CursorGrabMode;
@@ -60,7 +63,19 @@ impl Plugin for UiPlugins {
         app.add_state::<UiState>()
             .add_state::<MouseGrabState>()
             .init_resource::<ViewportRect>()
-            .add_plugins((DebugUiPlugins, MenuPlugins, GameMenuPlugins))
+            .add_plugins((
+                DebugUiPlugins,
+                MenuPlugins,
+                GameMenuPlugins,
+                LoadingPlugins,
+                NoticePlugins,
+                ClockPlugins,
+                HealthPlugins,
+                DeathScreenPlugins,
+                AmmoPlugins,
+                TrajectoryPlugins,
+                ScoreboardPlugins,
+            ))
             .add_systems(OnEnter(MouseGrabState::Enable), grab_mouse_on)
             .add_systems(OnEnter(MouseGrabState::Disable), grab_mouse_off);
     }
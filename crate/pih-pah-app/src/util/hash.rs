@@ -0,0 +1,8 @@
+use sha2::{Digest, Sha256};
+
+/// Returns the hex encoded SHA-256 hash of the data.
+///
+/// Used to identify files shared between host and clients, e.g. maps.
+pub fn content_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
@@ -3,6 +3,7 @@
 mod util;
 
 pub mod extend_commands;
+pub mod hash;
 pub mod i18n;
 
 pub use util::*;
@@ -3,7 +3,8 @@ use crate::actor::ActorPlugins;
 use crate::character::CharacterPlugins;
 use crate::component::{AxisName, ComponentPlugins, DespawnReason, NoclipDuration, Respawn};
 use crate::lobby::{Inputs, LobbyPlugins, LobbyState, PlayerInputs};
-use crate::map::{MapPlugins, SpawnPoint};
+use crate::map::{MapPlugins, SpawnPoint, TeamSpawn};
+use crate::mode::{ControlZone, Flag, GameModePlugins};
 use crate::settings::SettingsPlugins;
 use crate::sound::SoundPlugins;
 use crate::ui::GameMenuActionState;
@@ -14,6 +15,7 @@ use bevy::prelude::*;
 use bevy_xpbd_3d::components::Mass;
 use bevy_xpbd_3d::prelude::{Collider, PhysicsLayer, RigidBody};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Enum representing collision layers for physics interactions.
 #[derive(PhysicsLayer)]
@@ -65,12 +67,65 @@ impl ProjectileIdSeq {
     }
 }
 
+/// Index of the entities by their [`LinkId`], kept in sync by [`index_links`].
+///
+/// Snapshots and actor messages find their entities in it without scanning all linked entities.
+#[derive(Resource, Default, Debug)]
+pub struct LinkIndex {
+    entities: HashMap<LinkId, Entity>,
+    links: HashMap<Entity, LinkId>,
+}
+
+impl LinkIndex {
+    /// Returns the entity with the link id.
+    pub fn get(&self, link_id: &LinkId) -> Option<Entity> {
+        self.entities.get(link_id).copied()
+    }
+
+    /// Returns all linked entities.
+    pub fn iter(&self) -> impl Iterator<Item = (&LinkId, Entity)> {
+        self.entities
+            .iter()
+            .map(|(link_id, entity)| (link_id, *entity))
+    }
+
+    fn insert(&mut self, entity: Entity, link_id: LinkId) {
+        self.remove(entity);
+        self.entities.insert(link_id.clone(), entity);
+        self.links.insert(entity, link_id);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(link_id) = self.links.remove(&entity) {
+            if self.entities.get(&link_id) == Some(&entity) {
+                self.entities.remove(&link_id);
+            }
+        }
+    }
+}
+
+/// Adds spawned linked entities to the [`LinkIndex`] and removes despawned ones.
+pub fn index_links(
+    mut link_index: ResMut<LinkIndex>,
+    link_query: Query<(Entity, &LinkId), Changed<LinkId>>,
+    mut removed_links: RemovedComponents<LinkId>,
+) {
+    for entity in removed_links.read() {
+        link_index.remove(entity);
+    }
+    for (entity, link_id) in link_query.iter() {
+        link_index.insert(entity, link_id.clone());
+    }
+}
+
 pub struct WorldPlugins;
 
 impl Plugin for WorldPlugins {
     fn build(&self, app: &mut App) {
         app.init_resource::<ProjectileIdSeq>()
+            .init_resource::<LinkIndex>()
             .register_type::<ProjectileIdSeq>()
+            .add_systems(PreUpdate, index_links)
             .add_systems(Update, input)
             .add_plugins((
                 SettingsPlugins,
@@ -81,6 +136,7 @@ impl Plugin for WorldPlugins {
                 ActorPlugins,
                 ComponentPlugins,
                 CharacterPlugins,
+                GameModePlugins,
             ))
             .add_systems(
                 Update,
@@ -142,6 +198,21 @@ pub fn input(
                 turn_vertical = -ev.delta.y;
             }
 
+            let weapon = [
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+                KeyCode::Key9,
+            ]
+            .into_iter()
+            .position(|key| keyboard_input.pressed(key))
+            .map(|index| index as u8);
+
             let input = Inputs {
                 left: keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left),
                 right: keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right),
@@ -155,6 +226,10 @@ pub fn input(
                 fire: buttons
                     .get_pressed()
                     .any(|button| *button == MouseButton::Left),
+                weapon,
+                reload: keyboard_input.pressed(KeyCode::R),
+                // set by the shot prediction of clients
+                shot: player_input.get().shot,
             };
 
             player_input.insert_inputs(input);
@@ -243,6 +318,24 @@ fn process_scene_child(
                             .insert(LinkId::Scene(val.to_string()));
                     } else if name == "m" {
                         commands.entity(entity).insert(Mass(val.parse().unwrap()));
+                    } else if name == "f" {
+                        match val.parse() {
+                            Ok(team) => {
+                                commands
+                                    .entity(entity)
+                                    .insert((Flag::new(team), Flag::link_id(team)));
+                            }
+                            Err(err) => log::error!("Failed to read flag: {}", err),
+                        }
+                    } else if name == "z" {
+                        commands.entity(entity).insert(ControlZone::new(val));
+                    } else if name == "t" {
+                        match val.parse() {
+                            Ok(team) => {
+                                commands.entity(entity).insert(TeamSpawn(team));
+                            }
+                            Err(err) => log::error!("Failed to read team spawn point: {}", err),
+                        }
                     }
                 } else if name == "r" {
                     let transform = transform_query.get(entity).unwrap();
@@ -324,6 +417,14 @@ fn process_scene_child_simplified(
                         commands
                             .entity(entity)
                             .insert(LinkId::Scene(val.to_string()));
+                    } else if name == "f" {
+                        if let Ok(team) = val.parse() {
+                            commands
+                                .entity(entity)
+                                .insert((Flag::new(team), Flag::link_id(team)));
+                        }
+                    } else if name == "z" {
+                        commands.entity(entity).insert(ControlZone::new(val));
                     }
                 }
             }
@@ -0,0 +1,143 @@
+//! Remote admin console client of a pih-pah lobby.
+//!
+//! ```sh
+//! pih-pah-rcon --password secret stats
+//! pih-pah-rcon --port 5001 --password secret   # interactive
+//! ```
+//!
+//! The password can also be given with the `PIH_PAH_RCON_PASSWORD` environment variable.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+
+const DEFAULT_PORT: u16 = 5001;
+const PASSWORD_VAR: &str = "PIH_PAH_RCON_PASSWORD";
+
+struct Args {
+    port: u16,
+    password: Option<String>,
+    command: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        port: DEFAULT_PORT,
+        password: env::var(PASSWORD_VAR).ok(),
+        command: None,
+    };
+
+    let mut command = Vec::new();
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--port" => {
+                let port = iter.next().ok_or("--port needs a value")?;
+                args.port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+            }
+            "--password" => {
+                args.password = Some(iter.next().ok_or("--password needs a value")?);
+            }
+            "--help" | "-h" => {
+                return Err(
+                    "usage: pih-pah-rcon [--port <port>] [--password <password>] [command]"
+                        .to_string(),
+                )
+            }
+            _ => command.push(arg),
+        }
+    }
+    if !command.is_empty() {
+        args.command = Some(command.join(" "));
+    }
+
+    Ok(args)
+}
+
+/// Sends one command line and returns the response, which ends with an empty line.
+fn send(
+    stream: &mut TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    command: &str,
+) -> io::Result<Vec<String>> {
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+
+    let mut response = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by the server",
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok(response);
+        }
+        response.push(line.to_string());
+    }
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let mut stream = TcpStream::connect(("127.0.0.1", args.port))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    if let Some(password) = args.password {
+        let response = send(&mut stream, &mut reader, &format!("auth {}", password))?;
+        if response.first().map(String::as_str) != Some("ok") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                response.join("\n"),
+            ));
+        }
+    }
+
+    if let Some(command) = args.command {
+        for line in send(&mut stream, &mut reader, &command)? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut command = String::new();
+        if stdin.lock().read_line(&mut command)? == 0 {
+            return Ok(());
+        }
+        let command = command.trim();
+        if command.is_empty() {
+            continue;
+        }
+        if command == "quit" {
+            stream.write_all(b"quit\n")?;
+            return Ok(());
+        }
+        for line in send(&mut stream, &mut reader, command)? {
+            println!("{}", line);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("rcon: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}